@@ -0,0 +1,689 @@
+//! Submodule for broadphase structures, which find the pairs of
+//! objects that may be touching before testing them for real.
+//! Every structure implements `Broadphase`, so that each level may
+//! pick the one which suits its shape best.
+
+use types::Vector2;
+use std::rc::Rc;
+use std::collections::HashMap;
+use common::objects::{ AsGameObject, GameObject, ObjectRef };
+use collision::layers::can_collide;
+use collision::primitives::{ Collidable, Contact, Sweepable, AABB, Circle, RaycastHit };
+use collision::partitioning::Quadtree;
+use collision::grid::SpatialGrid;
+use collision::sweep_prune::SweepAndPrune;
+
+type BroadphasePair<T> = (ObjectRef<T>, ObjectRef<T>);
+type SweptContacts<T> = Vec<(ObjectRef<T>, ObjectRef<T>, Contact)>;
+
+/// Common trait to structures which keep track of where objects
+/// are, so that collisions and queries only look at nearby ones.
+pub trait Broadphase<T: ?Sized + GameObject> {
+    /// Adds a game object. Objects are told apart by id, so adding
+    /// one with the id of another replaces it.
+    /// # Arguments
+    /// * `object` - A dynamically-allocated object which should be added.
+    fn add(&mut self, object: ObjectRef<T>);
+
+    /// Removes a game object, given its id.
+    /// # Arguments
+    /// * `id` - Id of the object which should be removed.
+    fn remove_by_id(&mut self, id: u32) -> Result<ObjectRef<T>, &'static str>;
+
+    /// Removes a game object.
+    /// # Arguments
+    /// * `object` - A reference to the dynamically-allocated object which should be removed
+    fn remove(&mut self, object: ObjectRef<T>) -> Result<ObjectRef<T>, &'static str> {
+        let id = object.borrow().get_id();
+        self.remove_by_id(id)
+    }
+
+    /// Schedules a game object for positioning update.
+    /// # Arguments
+    /// * `object` - A reference to the dynamically-allocated object which should be updated
+    fn schedule_update(&mut self, object: ObjectRef<T>);
+
+    /// Updates pending objects which need repositioning.
+    /// Objects which fail to be relocated don't stop the others;
+    /// their ids are yielded along with the reason, instead.
+    fn update_positions(&mut self) -> Result<(), Vec<(u32, &'static str)>>;
+
+    /// Tests all collisions between objects, calling each object's
+    /// collision callbacks. See `CollisionEvents`.
+    fn test_collisions(&mut self);
+
+    /// Yields every object.
+    fn objects(&self) -> Vec<ObjectRef<T>>;
+
+    /// Yields the objects which are somewhere near a bounding
+    /// circle. Structures may yield more or fewer objects than the
+    /// ones which really overlap it, as long as it's quick; use
+    /// `query_circle` for an exact answer.
+    /// # Arguments
+    /// * `bounds` - Circle to look around.
+    fn local_objects(&self, bounds: Circle) -> Vec<ObjectRef<T>>;
+
+    /// Yields every object whose bounding circle overlaps a box.
+    /// # Arguments
+    /// * `aabb` - Box to be queried.
+    fn query_aabb(&self, aabb: &AABB) -> Vec<ObjectRef<T>>;
+
+    /// Yields every object whose bounding circle overlaps a circle.
+    /// # Arguments
+    /// * `circle` - Circle to be queried.
+    fn query_circle(&self, circle: &Circle) -> Vec<ObjectRef<T>>;
+
+    /// Yields every object whose bounding circle contains a point.
+    /// # Arguments
+    /// * `point` - Point to be queried.
+    fn query_point(&self, point: Vector2) -> Vec<ObjectRef<T>>;
+
+    /// Casts a ray against the bounding circles of the objects,
+    /// yielding the nearest object hit, if any.
+    /// # Arguments
+    /// * `origin` - Point where the ray starts.
+    ///
+    /// * `dir` - Direction of the ray. Does not need to be normalized.
+    ///
+    /// * `max_dist` - How far the ray goes.
+    fn raycast(&self, origin: Vector2, dir: Vector2, max_dist: f64) -> Option<(ObjectRef<T>, RaycastHit)>;
+}
+
+/// Describes a broadphase structure to be built.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BroadphaseKind {
+    /// A quadtree over a rectangular space, given by its center and
+    /// half-sizes. See `Quadtree::with_bounds`.
+    Quadtree { center: Vector2, halfws: [f64; 2], max_depth: u32 },
+    /// A loose quadtree. See `Quadtree::loose_with_bounds`.
+    LooseQuadtree { center: Vector2, halfws: [f64; 2], max_depth: u32, looseness: f64 },
+    /// A uniform grid of square cells. See `SpatialGrid::new`.
+    Grid { cell_size: f64 },
+    /// Objects sorted along the X axis. Fits long horizontal levels.
+    SweepAndPrune,
+}
+
+impl BroadphaseKind {
+    /// Builds an empty broadphase of this kind.
+    pub fn build<T: 'static + ?Sized + GameObject + AsGameObject>(&self) -> Box<Broadphase<T>> {
+        match *self {
+            BroadphaseKind::Quadtree { center, halfws, max_depth } =>
+                Box::new(Quadtree::with_bounds(&AABB { center: center, halfws: halfws }, max_depth)),
+            BroadphaseKind::LooseQuadtree { center, halfws, max_depth, looseness } =>
+                Box::new(Quadtree::loose_with_bounds(&AABB { center: center, halfws: halfws }, max_depth,
+                                                     looseness)),
+            BroadphaseKind::Grid { cell_size } =>
+                Box::new(SpatialGrid::new(cell_size)),
+            BroadphaseKind::SweepAndPrune =>
+                Box::new(SweepAndPrune::new()),
+        }
+    }
+}
+
+/// Keeps track of which pairs of objects overlap from one
+/// collision pass to the next, so that objects are told whether
+/// they started touching, kept touching or stopped touching.
+/// Pairs are told apart by object id.
+pub struct CollisionEvents<T: ?Sized + GameObject> {
+    // Pairs overlapping on the last collision pass, keyed by their
+    // ids, lowest first. Pairs found on the current pass go on the
+    // list until they're dispatched.
+    overlapping: HashMap<(u32, u32), BroadphasePair<T>>,
+    found:       Vec<(ObjectRef<T>, ObjectRef<T>, Contact)>,
+}
+
+impl<T: ?Sized + GameObject> CollisionEvents<T> {
+    /// Creates a new event tracker, with no overlapping pairs.
+    pub fn new() -> CollisionEvents<T> {
+        CollisionEvents {
+            overlapping: HashMap::new(),
+            found:       vec![],
+        }
+    }
+
+    /// Records that two objects overlap on the current pass.
+    /// # Arguments
+    /// * `obj_a` - First object of the pair.
+    ///
+    /// * `obj_b` - Second object of the pair.
+    ///
+    /// * `contact` - How the objects overlap, as seen by the first one.
+    pub fn push(&mut self, obj_a: ObjectRef<T>, obj_b: ObjectRef<T>, contact: Contact) {
+        self.found.push((obj_a, obj_b, contact));
+    }
+
+    /// Tests whether two objects which may be touching really do,
+    /// recording them if so. Fast objects are left for
+    /// `sweep_fast_objects`, and pairs whose collision layers don't
+    /// match are discarded. Yields whether the pair was tested at all.
+    /// # Arguments
+    /// * `obj_a` - First object of the pair.
+    ///
+    /// * `obj_b` - Second object of the pair.
+    pub fn test_pair(&mut self, obj_a: &ObjectRef<T>, obj_b: &ObjectRef<T>) -> bool {
+        // Fast objects are tested on their own pass
+        if obj_a.borrow().is_fast() || obj_b.borrow().is_fast() {
+            return false;
+        }
+
+        // Discard pairs which don't want each other
+        if !layers_match(&*obj_a.borrow(), &*obj_b.borrow()) {
+            return false;
+        }
+
+        // Test collision
+        let bounding_a = obj_a.borrow().bounding_circle();
+        let bounding_b = obj_b.borrow().bounding_circle();
+
+        if let Some(contact) = bounding_a.contact(&bounding_b) {
+            // Callbacks are triggered once every pair is known
+            self.push(obj_a.clone(), obj_b.clone(), contact);
+        }
+        true
+    }
+}
+
+impl<T: ?Sized + GameObject> Default for CollisionEvents<T> {
+    fn default() -> Self {
+        CollisionEvents::new()
+    }
+}
+
+impl<T: 'static + ?Sized + GameObject + AsGameObject> CollisionEvents<T> {
+    /// Calls each object's `on_collision_enter`, `on_collision_stay`
+    /// and `on_collision_exit` callbacks, according to the pairs
+    /// recorded on this pass and the ones which overlapped on the
+    /// last one. Pairs recorded more than once on the same pass
+    /// only get their callbacks called once.
+    pub fn dispatch(&mut self) {
+        let mut overlapping = HashMap::new();
+
+        for (obj_a, obj_b, contact) in self.found.drain(..) {
+            // Key pairs by id, lowest first
+            let id_a = obj_a.borrow().get_id();
+            let id_b = obj_b.borrow().get_id();
+            let (key, obj_a, obj_b, contact) =
+                if id_a <= id_b {
+                    ((id_a, id_b), obj_a, obj_b, contact)
+                } else {
+                    ((id_b, id_a), obj_b, obj_a, contact.flipped())
+                };
+
+            // Pairs found twice on a pass are only dispatched once
+            if overlapping.contains_key(&key) {
+                continue;
+            }
+
+            // Pass along a clone of the counted reference
+            if self.overlapping.remove(&key).is_some() {
+                obj_a.borrow_mut().on_collision_stay(T::as_game_object(&obj_b), contact);
+                obj_b.borrow_mut().on_collision_stay(T::as_game_object(&obj_a), contact.flipped());
+            } else {
+                obj_a.borrow_mut().on_collision_enter(T::as_game_object(&obj_b), contact);
+                obj_b.borrow_mut().on_collision_enter(T::as_game_object(&obj_a), contact.flipped());
+            }
+            overlapping.insert(key, (obj_a, obj_b));
+        }
+
+        // Whatever is left didn't overlap this time
+        for (_, (obj_a, obj_b)) in self.overlapping.drain() {
+            obj_a.borrow_mut().on_collision_exit(T::as_game_object(&obj_b));
+            obj_b.borrow_mut().on_collision_exit(T::as_game_object(&obj_a));
+        }
+
+        self.overlapping = overlapping;
+    }
+}
+
+/// Whether the collision layers of two objects allow them to collide.
+pub fn layers_match<T: ?Sized + GameObject>(a: &T, b: &T) -> bool {
+    can_collide(a.category(), a.collides_with(), b.category(), b.collides_with())
+}
+
+/// Sweeps fast objects along their last displacement, against
+/// everything near their path, so that they don't go through
+/// anything in a single step. Yields how many pairs were tested,
+/// along with the pairs which collided and how.
+/// # Arguments
+/// * `fast` - Objects flagged as fast.
+///
+/// * `query` - Yields the objects whose bounding circles overlap a box.
+pub fn sweep_fast_objects<T, Q>(fast: &[ObjectRef<T>], query: Q) -> (usize, SweptContacts<T>)
+    where T: ?Sized + GameObject, Q: Fn(&AABB) -> Vec<ObjectRef<T>> {
+    let paths: Vec<SweptPath> = fast.iter().map(|object| SweptPath::of(&*object.borrow())).collect();
+    let mut tested = 0;
+    let mut found = vec![];
+    for (index, obj_a) in fast.iter().enumerate() {
+        let path_a = &paths[index];
+
+        // Slow objects near the path of the object. Fast ones may
+        // have been far from it by the end of the step, so they're
+        // matched by their whole path instead, and only once.
+        let mut candidates: Vec<(ObjectRef<T>, SweptPath)> = query(&path_a.aabb).into_iter()
+            .filter(|object| !object.borrow().is_fast())
+            .map(|object| {
+                let path = SweptPath::of(&*object.borrow());
+                (object, path)
+            })
+            .collect();
+        for (obj_b, path_b) in fast.iter().zip(paths.iter()).skip(index + 1) {
+            if path_a.aabb.collides(&path_b.aabb) {
+                candidates.push((obj_b.clone(), path_b.clone()));
+            }
+        }
+
+        for (obj_b, path_b) in candidates {
+            if Rc::ptr_eq(obj_a, &obj_b) {
+                continue;
+            }
+
+            if !layers_match(&*obj_a.borrow(), &*obj_b.borrow()) {
+                continue;
+            }
+
+            tested += 1;
+            if let Some(contact) = path_a.contact(&path_b) {
+                found.push((obj_a.clone(), obj_b, contact));
+            }
+        }
+    }
+    (tested, found)
+}
+
+/// Bounding circle of an object at the start of its last step,
+/// how much it moved, and the box around its whole path.
+#[derive(Clone)]
+struct SweptPath {
+    start:  Circle,
+    motion: Vector2,
+    aabb:   AABB,
+}
+
+impl SweptPath {
+    fn of<T: ?Sized + GameObject>(object: &T) -> SweptPath {
+        let mut start = object.bounding_circle();
+        let motion = object.displacement();
+        start.center = start.center - motion;
+
+        let end = start.center + motion;
+        let min = Vector2::new(start.center.x.min(end.x) - start.radius,
+                               start.center.y.min(end.y) - start.radius);
+        let max = Vector2::new(start.center.x.max(end.x) + start.radius,
+                               start.center.y.max(end.y) + start.radius);
+        SweptPath {
+            start:  start,
+            motion: motion,
+            aabb:   AABB {
+                center: (min + max) * 0.5,
+                halfws: [(max.x - min.x) * 0.5, (max.y - min.y) * 0.5],
+            },
+        }
+    }
+
+    /// Sweeps this path against another one, as seen by this one.
+    fn contact(&self, other: &SweptPath) -> Option<Contact> {
+        // Sweep with the relative motion, as if the other stood still
+        let motion = self.motion - other.motion;
+        let hit = match self.start.sweep(motion, &other.start) {
+            Some(hit) if hit.toi <= 1.0 => hit,
+            _ => return None,
+        };
+
+        // Still overlapping at the end of the step: report it as
+        // usual. Otherwise, report how far this one went past the
+        // moment of impact.
+        let end = Circle { center: self.start.center + self.motion, radius: self.start.radius };
+        let other_end = Circle { center: other.start.center + other.motion, radius: other.start.radius };
+        Some(match end.contact(&other_end) {
+            Some(contact) => contact,
+            None => {
+                let impact_center = self.start.center + motion * hit.toi;
+                Contact {
+                    normal: hit.normal,
+                    depth:  -(motion * (1.0 - hit.toi)).dot(hit.normal),
+                    point:  impact_center - hit.normal * self.start.radius,
+                }
+            },
+        })
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use common::objects::wrap_to_ref;
+    use common::rng::Rng;
+    use collision::primitives::Raycastable;
+    use game::world::World;
+
+    /// A plain circle, which keeps the ids of whatever it touches.
+    /// Shared with the tests of each structure.
+    pub struct Dot {
+        pub id:       u32,
+        pub bounds:   Circle,
+        pub touching: Vec<u32>,
+    }
+
+    impl GameObject for Dot {
+        fn update(&mut self, _dt: f64, _world: &World) { }
+
+        fn bounding_circle(&self) -> Circle {
+            self.bounds.clone()
+        }
+
+        fn get_position(&self) -> Vector2 {
+            self.bounds.center
+        }
+
+        fn get_id(&self) -> u32 {
+            self.id
+        }
+
+        fn on_collision_enter(&mut self, other: ObjectRef<GameObject>, _contact: Contact) {
+            self.touching.push(other.borrow().get_id());
+        }
+    }
+
+    pub fn dot(id: u32, center: Vector2, radius: f64) -> ObjectRef<Dot> {
+        wrap_to_ref(Dot { id: id, bounds: Circle { center: center, radius: radius }, touching: vec![] })
+    }
+
+    fn kinds() -> Vec<BroadphaseKind> {
+        vec![
+            BroadphaseKind::Quadtree { center: Vector2::zero(), halfws: [400.0, 400.0], max_depth: 4 },
+            BroadphaseKind::LooseQuadtree { center: Vector2::zero(), halfws: [400.0, 400.0], max_depth: 4,
+                                            looseness: 2.0 },
+            BroadphaseKind::Grid { cell_size: 64.0 },
+            BroadphaseKind::SweepAndPrune,
+        ]
+    }
+
+    /// Fills a broadphase with objects, some of them beyond the
+    /// bounds of the quadtrees, and moves a few of them around.
+    fn random_broadphase(kind: BroadphaseKind, seed: u64) -> (Box<Broadphase<Dot>>, Vec<ObjectRef<Dot>>) {
+        let mut rng = Rng::new(seed);
+        let mut broadphase = kind.build();
+        let mut dots = vec![];
+        for id in 0..300 {
+            let center = Vector2::new(rng.range(-1200.0, 1200.0), rng.range(-450.0, 450.0));
+            let dot = dot(id, center, rng.range(1.0, 60.0));
+            broadphase.add(dot.clone());
+            dots.push(dot);
+        }
+
+        for dot in dots.iter().filter(|dot| dot.borrow().id % 7 == 0) {
+            dot.borrow_mut().bounds.center = Vector2::new(rng.range(-1200.0, 1200.0), rng.range(-450.0, 450.0));
+            broadphase.schedule_update(dot.clone());
+        }
+        assert!(broadphase.update_positions().is_ok());
+        (broadphase, dots)
+    }
+
+    pub fn sorted_ids(objects: &[ObjectRef<Dot>]) -> Vec<u32> {
+        let mut ids: Vec<u32> = objects.iter().map(|object| object.borrow().id).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn queries_match_brute_force() {
+        for kind in kinds() {
+            let (broadphase, dots) = random_broadphase(kind, 1);
+            assert_eq!(sorted_ids(&broadphase.objects()), sorted_ids(&dots));
+
+            let mut rng = Rng::new(2);
+            for _ in 0..100 {
+                let center = Vector2::new(rng.range(-1300.0, 1300.0), rng.range(-500.0, 500.0));
+                let aabb = AABB { center: center, halfws: [rng.range(0.0, 200.0), rng.range(0.0, 200.0)] };
+                let circle = Circle { center: center, radius: rng.range(0.0, 200.0) };
+
+                let expected: Vec<ObjectRef<Dot>> = dots.iter()
+                    .filter(|dot| dot.borrow().bounds.collides(&aabb)).cloned().collect();
+                assert_eq!(sorted_ids(&broadphase.query_aabb(&aabb)), sorted_ids(&expected));
+
+                let expected: Vec<ObjectRef<Dot>> = dots.iter()
+                    .filter(|dot| dot.borrow().bounds.collides(&circle)).cloned().collect();
+                assert_eq!(sorted_ids(&broadphase.query_circle(&circle)), sorted_ids(&expected));
+
+                let expected: Vec<ObjectRef<Dot>> = dots.iter()
+                    .filter(|dot| {
+                        let bounds = &dot.borrow().bounds;
+                        (center - bounds.center).length() <= bounds.radius
+                    }).cloned().collect();
+                assert_eq!(sorted_ids(&broadphase.query_point(center)), sorted_ids(&expected));
+            }
+        }
+    }
+
+    #[test]
+    fn raycasts_match_brute_force() {
+        for kind in kinds() {
+            let (broadphase, dots) = random_broadphase(kind, 3);
+            let mut rng = Rng::new(4);
+            for _ in 0..100 {
+                let origin = Vector2::new(rng.range(-1500.0, 1500.0), rng.range(-600.0, 600.0));
+                let dir = Vector2::new(rng.range(-1.0, 1.0), rng.range(-1.0, 1.0));
+                let max_dist = if rng.next_f64() < 0.5 { rng.range(0.0, 800.0) } else { ::std::f64::INFINITY };
+
+                let expected = dots.iter()
+                    .filter_map(|dot| dot.borrow().bounds.raycast(origin, dir, max_dist))
+                    .map(|hit| hit.t)
+                    .fold(None, |best: Option<f64>, t| Some(best.map_or(t, |best| best.min(t))));
+                let found = broadphase.raycast(origin, dir, max_dist).map(|(_, hit)| hit.t);
+                // The broadphases normalize the direction first, which
+                // may round the distance slightly differently
+                match (found, expected) {
+                    (Some(found), Some(expected)) => assert!((found - expected).abs() < 1e-9),
+                    (found, expected) => assert_eq!(found, expected),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn collisions_match_brute_force() {
+        for kind in kinds() {
+            let (mut broadphase, dots) = random_broadphase(kind, 5);
+            broadphase.test_collisions();
+
+            for dot in &dots {
+                let mut expected: Vec<u32> = dots.iter()
+                    .filter(|other| other.borrow().id != dot.borrow().id)
+                    .filter(|other| other.borrow().bounds.collides(&dot.borrow().bounds))
+                    .map(|other| other.borrow().id)
+                    .collect();
+                expected.sort();
+
+                let mut touching = dot.borrow().touching.clone();
+                touching.sort();
+                assert_eq!(touching, expected);
+            }
+        }
+    }
+
+    /// Records every collision callback it gets.
+    struct Recorder {
+        id:     u32,
+        events: Vec<(&'static str, u32)>,
+    }
+
+    impl GameObject for Recorder {
+        fn update(&mut self, _dt: f64, _world: &World) { }
+
+        fn bounding_circle(&self) -> Circle {
+            Circle { center: Vector2::zero(), radius: 1.0 }
+        }
+
+        fn get_position(&self) -> Vector2 {
+            Vector2::zero()
+        }
+
+        fn get_id(&self) -> u32 {
+            self.id
+        }
+
+        fn on_collision_enter(&mut self, other: ObjectRef<GameObject>, _contact: Contact) {
+            self.events.push(("enter", other.borrow().get_id()));
+        }
+
+        fn on_collision_stay(&mut self, other: ObjectRef<GameObject>, _contact: Contact) {
+            self.events.push(("stay", other.borrow().get_id()));
+        }
+
+        fn on_collision_exit(&mut self, other: ObjectRef<GameObject>) {
+            self.events.push(("exit", other.borrow().get_id()));
+        }
+    }
+
+    #[test]
+    fn events_enter_stay_and_exit() {
+        let a = wrap_to_ref(Recorder { id: 1, events: vec![] });
+        let b = wrap_to_ref(Recorder { id: 2, events: vec![] });
+        let contact = a.borrow().bounding_circle().contact(&b.borrow().bounding_circle()).unwrap();
+        let mut events = CollisionEvents::new();
+
+        // Found twice, in both orders
+        events.push(a.clone(), b.clone(), contact);
+        events.push(b.clone(), a.clone(), contact.flipped());
+        events.dispatch();
+
+        events.push(a.clone(), b.clone(), contact);
+        events.dispatch();
+
+        events.dispatch();
+        events.dispatch();
+
+        let expected = |other| vec![("enter", other), ("stay", other), ("exit", other)];
+        assert_eq!(a.borrow().events, expected(2));
+        assert_eq!(b.borrow().events, expected(1));
+    }
+
+    /// An object which moved in a straight line on its last step.
+    struct Bullet {
+        id:       u32,
+        end:      Vector2,
+        motion:   Vector2,
+        fast:     bool,
+        touching: Vec<u32>,
+    }
+
+    impl GameObject for Bullet {
+        fn update(&mut self, _dt: f64, _world: &World) { }
+
+        fn bounding_circle(&self) -> Circle {
+            Circle { center: self.end, radius: 5.0 }
+        }
+
+        fn get_position(&self) -> Vector2 {
+            self.end
+        }
+
+        fn get_id(&self) -> u32 {
+            self.id
+        }
+
+        fn is_fast(&self) -> bool {
+            self.fast
+        }
+
+        fn displacement(&self) -> Vector2 {
+            self.motion
+        }
+
+        fn on_collision_enter(&mut self, other: ObjectRef<GameObject>, _contact: Contact) {
+            self.touching.push(other.borrow().get_id());
+        }
+    }
+
+    fn bullet(id: u32, start: Vector2, end: Vector2, fast: bool) -> ObjectRef<Bullet> {
+        wrap_to_ref(Bullet { id: id, end: end, motion: end - start, fast: fast, touching: vec![] })
+    }
+
+    #[test]
+    fn fast_objects_meet_along_their_paths() {
+        for kind in kinds() {
+            let mut broadphase: Box<Broadphase<Bullet>> = kind.build();
+            let bullets = vec![
+                // Goes through a slow one
+                bullet(1, Vector2::new(-100.0, 200.0), Vector2::new(100.0, 200.0), true),
+                bullet(2, Vector2::new(0.0, 200.0), Vector2::new(0.0, 200.0), false),
+                // Cross each other, ending far from the other's path
+                bullet(3, Vector2::new(-100.0, 0.0), Vector2::new(100.0, 0.0), true),
+                bullet(4, Vector2::new(0.0, -100.0), Vector2::new(0.0, 100.0), true),
+                // Swap places
+                bullet(5, Vector2::new(-100.0, -200.0), Vector2::new(100.0, -200.0), true),
+                bullet(6, Vector2::new(100.0, -200.0), Vector2::new(-100.0, -200.0), true),
+                // Cross the path of 3 after it's gone
+                bullet(7, Vector2::new(50.0, -100.0), Vector2::new(-50.0, -20.0), true),
+            ];
+            for bullet in &bullets {
+                broadphase.add(bullet.clone());
+            }
+            broadphase.test_collisions();
+
+            let touching: Vec<Vec<u32>> = bullets.iter().map(|bullet| bullet.borrow().touching.clone()).collect();
+            assert_eq!(touching, vec![vec![2], vec![1], vec![4], vec![3], vec![6], vec![5], vec![]]);
+        }
+    }
+
+    #[test]
+    fn adding_an_id_twice_replaces_the_object() {
+        for kind in kinds() {
+            let mut broadphase: Box<Broadphase<Dot>> = kind.build();
+            let (old, new) = (dot(1, Vector2::new(-100.0, 0.0), 10.0), dot(1, Vector2::new(100.0, 0.0), 10.0));
+            broadphase.add(old);
+            broadphase.add(new.clone());
+
+            let objects = broadphase.objects();
+            assert_eq!(objects.len(), 1);
+            assert!(Rc::ptr_eq(&objects[0], &new));
+            assert!(broadphase.query_point(Vector2::new(-100.0, 0.0)).is_empty());
+
+            assert!(broadphase.remove_by_id(1).is_ok());
+            assert!(broadphase.objects().is_empty());
+            assert!(broadphase.remove_by_id(1).is_err());
+        }
+    }
+
+    struct Marker {
+        position: Vector2,
+        touched:  u32,
+    }
+
+    impl GameObject for Marker {
+        fn update(&mut self, _dt: f64, _world: &World) { }
+
+        fn bounding_circle(&self) -> Circle {
+            Circle { center: self.position, radius: 10.0 }
+        }
+
+        fn get_position(&self) -> Vector2 {
+            self.position
+        }
+
+        fn get_id(&self) -> u32 {
+            1000
+        }
+
+        fn on_collision_enter(&mut self, _other: ObjectRef<GameObject>, _contact: Contact) {
+            self.touched += 1;
+        }
+    }
+
+    #[test]
+    fn different_object_types_collide() {
+        for kind in kinds() {
+            let mut broadphase: Box<Broadphase<GameObject>> = kind.build();
+            let dot = dot(1, Vector2::new(5.0, 0.0), 10.0);
+            let marker = wrap_to_ref(Marker { position: Vector2::zero(), touched: 0 });
+            broadphase.add(dot.clone());
+            broadphase.add(marker.clone());
+            broadphase.test_collisions();
+
+            assert_eq!(dot.borrow().touching, vec![1000]);
+            assert_eq!(marker.borrow().touched, 1);
+            assert_eq!(broadphase.query_point(Vector2::new(-8.0, 0.0)).len(), 1);
+        }
+    }
+}
@@ -0,0 +1,444 @@
+//! Submodule for a uniform grid, also known as a spatial hash.
+//! Space is split in square cells of the same size, and objects are
+//! kept on every cell their bounding circle touches. Unlike the
+//! quadtree, the grid has no bounds, so it fits levels of any shape.
+
+use types::Vector2;
+use std::collections::{ BTreeMap, HashMap, HashSet };
+use std::collections::btree_map::Entry;
+use common::objects::{ AsGameObject, GameObject, ObjectRef };
+use collision::broadphase::{ Broadphase, CollisionEvents, sweep_fast_objects };
+use collision::primitives::{ Collidable, AABB, Circle, Raycastable, RaycastHit, raycast_slabs };
+
+type GridCell = (i64, i64);
+type GridRemoveResult<T> = Result<ObjectRef<T>, &'static str>;
+
+/// Most cells an object may be kept on. Larger objects, or objects
+/// with non-finite bounds, are kept apart and checked by every query
+/// instead, so that a single huge object can't fill memory with cells.
+const MAX_CELLS_PER_OBJECT: f64 = 256.0;
+
+/// Implements a uniform grid. Only cells holding objects are stored,
+/// so space may be as large as needed.
+pub struct SpatialGrid<T: ?Sized + GameObject> {
+    cell_size: f64,
+
+    // Cells are kept in order, so that iterating over them always
+    // goes the same way
+    cells: BTreeMap<GridCell, Vec<ObjectRef<T>>>,
+
+    // Cells where each object lives, by id. Oversized objects live
+    // on no cells
+    index: HashMap<u32, Vec<GridCell>>,
+
+    // Objects too large to be kept on cells
+    oversized: Vec<ObjectRef<T>>,
+
+    // Schedule for update
+    update_queue: Vec<ObjectRef<T>>,
+
+    events: CollisionEvents<T>,
+}
+
+// Constructor
+impl<T: ?Sized + GameObject> SpatialGrid<T> {
+    /// Creates a new grid.
+    /// # Arguments
+    /// * `cell_size` - Side of each cell. Should be a bit larger than
+    /// most objects, since objects are kept on every cell they touch.
+    pub fn new(cell_size: f64) -> SpatialGrid<T> {
+        SpatialGrid {
+            cell_size:    cell_size,
+            cells:        BTreeMap::new(),
+            index:        HashMap::new(),
+            oversized:    vec![],
+            update_queue: vec![],
+            events:       CollisionEvents::new(),
+        }
+    }
+
+    /// Yields the cell a point is on.
+    fn cell_of(&self, point: Vector2) -> GridCell {
+        ((point.x / self.cell_size).floor() as i64, (point.y / self.cell_size).floor() as i64)
+    }
+
+    /// Yields every cell a bounding circle touches, or nothing if it
+    /// touches more than `MAX_CELLS_PER_OBJECT` of them.
+    fn cells_of(&self, bounds: &Circle) -> Option<Vec<GridCell>> {
+        let reach = Vector2::new(bounds.radius, bounds.radius);
+        let (min, max) = (bounds.center - reach, bounds.center + reach);
+        let columns = (max.x / self.cell_size).floor() - (min.x / self.cell_size).floor() + 1.0;
+        let rows = (max.y / self.cell_size).floor() - (min.y / self.cell_size).floor() + 1.0;
+
+        // Non-finite bounds may yield NaN cells
+        let count = columns * rows;
+        if count.is_nan() || count > MAX_CELLS_PER_OBJECT {
+            return None;
+        }
+
+        let first = self.cell_of(min);
+        let last = self.cell_of(max);
+
+        let mut cells = vec![];
+        for x in first.0..last.0 + 1 {
+            for y in first.1..last.1 + 1 {
+                cells.push((x, y));
+            }
+        }
+        Some(cells)
+    }
+
+    /// Yields the stored cells which overlap a box, given by its
+    /// minimum and maximum points. Bounds may be infinite.
+    fn cells_between(&self, min: Vector2, max: Vector2) -> Vec<GridCell> {
+        let first = ((min.x / self.cell_size).floor(), (min.y / self.cell_size).floor());
+        let last  = ((max.x / self.cell_size).floor(), (max.y / self.cell_size).floor());
+
+        // Walking the box is only worth it when it has fewer cells
+        // than the grid stores
+        let area = (last.0 - first.0 + 1.0) * (last.1 - first.1 + 1.0);
+        if area <= self.cells.len() as f64 {
+            let mut cells = vec![];
+            for x in first.0 as i64..last.0 as i64 + 1 {
+                for y in first.1 as i64..last.1 as i64 + 1 {
+                    if self.cells.contains_key(&(x, y)) {
+                        cells.push((x, y));
+                    }
+                }
+            }
+            cells
+        } else {
+            self.cells.keys()
+                .filter(|&&(x, y)| {
+                    x as f64 >= first.0 && x as f64 <= last.0
+                        && y as f64 >= first.1 && y as f64 <= last.1
+                })
+                .cloned()
+                .collect()
+        }
+    }
+
+    /// Yields the region covered by the stored cells, as minimum
+    /// and maximum points.
+    fn occupied_region(&self) -> Option<(Vector2, Vector2)> {
+        let &(first_x, first_y) = self.cells.keys().next()?;
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (first_x, first_y, first_x, first_y);
+        for &(x, y) in self.cells.keys() {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+
+        let size = self.cell_size;
+        Some((Vector2::new(min_x as f64 * size, min_y as f64 * size),
+              Vector2::new((max_x + 1) as f64 * size, (max_y + 1) as f64 * size)))
+    }
+
+    fn collect<K>(&self, cells: Vec<GridCell>, keep: &K) -> Vec<ObjectRef<T>>
+        where K: Fn(&Circle) -> bool {
+        // Objects may be on many cells, but are only yielded once
+        let mut seen = HashSet::new();
+        let mut found = vec![];
+        for cell in cells {
+            if let Some(objects) = self.cells.get(&cell) {
+                for object in objects {
+                    let id = object.borrow().get_id();
+                    if seen.insert(id) && keep(&object.borrow().bounding_circle()) {
+                        found.push(object.clone());
+                    }
+                }
+            }
+        }
+
+        // Oversized objects may be anywhere
+        for object in &self.oversized {
+            if keep(&object.borrow().bounding_circle()) {
+                found.push(object.clone());
+            }
+        }
+        found
+    }
+
+    /// Casts a ray over the cells, visiting them in the order the ray
+    /// goes through them, and stopping as soon as nothing farther can
+    /// beat the nearest hit. Oversized objects are not checked.
+    /// # Arguments
+    /// * `origin` - Origin of the ray.
+    ///
+    /// * `dir` - Normalized direction of the ray.
+    ///
+    /// * `max_dist` - Farthest distance along the ray to look at.
+    fn raycast_cells(&self, origin: Vector2, dir: Vector2, max_dist: f64) -> Option<(ObjectRef<T>, RaycastHit)> {
+        // Only the stretch of the ray over stored cells matters
+        let (min, max) = self.occupied_region()?;
+        let (enter, _) = raycast_slabs(origin, dir, min, max)?;
+        let limit = max_dist.min(enter + (max - min).length());
+        if enter > limit {
+            return None;
+        }
+
+        // Distances along the ray to the next cell boundary on each
+        // axis, and between boundaries
+        let size = self.cell_size;
+        let infinity = ::std::f64::INFINITY;
+        let mut cell = self.cell_of(origin + dir * enter);
+        let step = (if dir.x > 0.0 { 1 } else { -1 }, if dir.y > 0.0 { 1 } else { -1 });
+        let boundary = ((if dir.x > 0.0 { cell.0 + 1 } else { cell.0 }) as f64 * size,
+                        (if dir.y > 0.0 { cell.1 + 1 } else { cell.1 }) as f64 * size);
+        let mut next = (if dir.x != 0.0 { (boundary.0 - origin.x) / dir.x } else { infinity },
+                        if dir.y != 0.0 { (boundary.1 - origin.y) / dir.y } else { infinity });
+        let delta = (if dir.x != 0.0 { size / dir.x.abs() } else { infinity },
+                     if dir.y != 0.0 { size / dir.y.abs() } else { infinity });
+
+        let mut nearest: Option<(ObjectRef<T>, RaycastHit)> = None;
+        loop {
+            if let Some(objects) = self.cells.get(&cell) {
+                for object in objects {
+                    let reach = match nearest {
+                        Some((_, ref hit)) => hit.t,
+                        None => limit,
+                    };
+                    let bounds = object.borrow().bounding_circle();
+                    if let Some(hit) = bounds.raycast(origin, dir, reach) {
+                        let closer = match nearest {
+                            Some((_, ref best)) => hit.t < best.t,
+                            None => true,
+                        };
+                        if closer {
+                            nearest = Some((object.clone(), hit));
+                        }
+                    }
+                }
+            }
+
+            // Objects on the next cells which weren't on this one
+            // can't be hit before the ray leaves it
+            let exit = next.0.min(next.1);
+            let reach = match nearest {
+                Some((_, ref hit)) => hit.t,
+                None => limit,
+            };
+            if exit > reach {
+                break;
+            }
+
+            if next.0 < next.1 {
+                cell.0 += step.0;
+                next.0 += delta.0;
+            } else {
+                cell.1 += step.1;
+                next.1 += delta.1;
+            }
+        }
+        nearest
+    }
+}
+
+// Broadphase interface
+impl<T: 'static + ?Sized + GameObject + AsGameObject> Broadphase<T> for SpatialGrid<T> {
+    fn add(&mut self, object: ObjectRef<T>) {
+        let id = object.borrow().get_id();
+        if self.index.contains_key(&id) {
+            let _ = self.remove_by_id(id);
+        }
+
+        let bounds = object.borrow().bounding_circle();
+        let cells = match self.cells_of(&bounds) {
+            Some(cells) => cells,
+            None => {
+                self.oversized.push(object);
+                self.index.insert(id, vec![]);
+                return;
+            },
+        };
+
+        for cell in &cells {
+            match self.cells.entry(*cell) {
+                Entry::Occupied(mut entry) => entry.get_mut().push(object.clone()),
+                Entry::Vacant(entry) => { entry.insert(vec![object.clone()]); },
+            }
+        }
+        self.index.insert(id, cells);
+    }
+
+    /// Cells are looked up by id, but each of them is then scanned
+    /// for the object.
+    fn remove_by_id(&mut self, id: u32) -> GridRemoveResult<T> {
+        let cells = self.index.remove(&id).ok_or("Object does not exist in this grid")?;
+        let mut removed = None;
+        for cell in cells {
+            let now_empty = match self.cells.get_mut(&cell) {
+                Some(objects) => {
+                    if let Some(index) = objects.iter().position(|object| object.borrow().get_id() == id) {
+                        removed = Some(objects.remove(index));
+                    }
+                    objects.is_empty()
+                },
+                None => false,
+            };
+
+            // Only cells holding objects are kept
+            if now_empty {
+                self.cells.remove(&cell);
+            }
+        }
+
+        if let Some(index) = self.oversized.iter().position(|object| object.borrow().get_id() == id) {
+            removed = Some(self.oversized.remove(index));
+        }
+        removed.ok_or("Object does not exist in this grid")
+    }
+
+    fn schedule_update(&mut self, object: ObjectRef<T>) {
+        self.update_queue.push(object);
+    }
+
+    fn update_positions(&mut self) -> Result<(), Vec<(u32, &'static str)>> {
+        let update_queue: Vec<_> = self.update_queue.drain(..).collect();
+        let mut failures = vec![];
+        for object in update_queue {
+            let id = object.borrow().get_id();
+            match self.remove_by_id(id) {
+                Ok(_) => self.add(object),
+                Err(reason) => failures.push((id, reason)),
+            }
+        }
+
+        if failures.is_empty() { Ok(()) } else { Err(failures) }
+    }
+
+    /// Only objects sharing a cell are tested.
+    fn test_collisions(&mut self) {
+        // Objects sharing many cells are only tested once
+        let mut tested = HashSet::new();
+        for objects in self.cells.values() {
+            for (i, obj_a) in objects.iter().enumerate() {
+                for obj_b in objects.iter().skip(i + 1) {
+                    let id_a = obj_a.borrow().get_id();
+                    let id_b = obj_b.borrow().get_id();
+                    let key = if id_a <= id_b { (id_a, id_b) } else { (id_b, id_a) };
+                    if tested.insert(key) {
+                        self.events.test_pair(obj_a, obj_b);
+                    }
+                }
+            }
+        }
+
+        // Oversized objects may touch anything
+        let objects = self.objects();
+        for obj_a in &self.oversized {
+            let id_a = obj_a.borrow().get_id();
+            for obj_b in &objects {
+                let id_b = obj_b.borrow().get_id();
+                let key = if id_a <= id_b { (id_a, id_b) } else { (id_b, id_a) };
+                if id_a != id_b && tested.insert(key) {
+                    self.events.test_pair(obj_a, obj_b);
+                }
+            }
+        }
+
+        let fast: Vec<ObjectRef<T>> = self.objects().into_iter()
+            .filter(|object| object.borrow().is_fast())
+            .collect();
+        let (_, found) = sweep_fast_objects(&fast, |aabb| self.query_aabb(aabb));
+        for (obj_a, obj_b, contact) in found {
+            self.events.push(obj_a, obj_b, contact);
+        }
+
+        self.events.dispatch();
+    }
+
+    fn objects(&self) -> Vec<ObjectRef<T>> {
+        let cells = self.cells.keys().cloned().collect();
+        self.collect(cells, &|_| true)
+    }
+
+    fn local_objects(&self, bounds: Circle) -> Vec<ObjectRef<T>> {
+        self.query_circle(&bounds)
+    }
+
+    fn query_aabb(&self, aabb: &AABB) -> Vec<ObjectRef<T>> {
+        let cells = self.cells_between(aabb.min(), aabb.max());
+        self.collect(cells, &|bounds| bounds.collides(aabb))
+    }
+
+    fn query_circle(&self, circle: &Circle) -> Vec<ObjectRef<T>> {
+        let reach = Vector2::new(circle.radius, circle.radius);
+        let cells = self.cells_between(circle.center - reach, circle.center + reach);
+        self.collect(cells, &|bounds| bounds.collides(circle))
+    }
+
+    fn query_point(&self, point: Vector2) -> Vec<ObjectRef<T>> {
+        let cells = self.cells_between(point, point);
+        self.collect(cells, &|bounds| (point - bounds.center).length() <= bounds.radius)
+    }
+
+    /// Cells are visited in the order the ray goes through them,
+    /// stopping as soon as nothing farther can beat the nearest hit.
+    /// Oversized objects are then checked one by one.
+    fn raycast(&self, origin: Vector2, dir: Vector2, max_dist: f64) -> Option<(ObjectRef<T>, RaycastHit)> {
+        let dir = dir.normalized();
+        if dir == Vector2::zero() {
+            return None;
+        }
+
+        let mut nearest = self.raycast_cells(origin, dir, max_dist);
+        for object in &self.oversized {
+            let reach = match nearest {
+                Some((_, ref hit)) => hit.t,
+                None => max_dist,
+            };
+            if let Some(hit) = object.borrow().bounding_circle().raycast(origin, dir, reach) {
+                let closer = match nearest {
+                    Some((_, ref best)) => hit.t < best.t,
+                    None => true,
+                };
+                if closer {
+                    nearest = Some((object.clone(), hit));
+                }
+            }
+        }
+        nearest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collision::broadphase::tests::{ Dot, dot, sorted_ids };
+
+    #[test]
+    fn oversized_objects_are_kept_off_cells() {
+        let mut grid: SpatialGrid<Dot> = SpatialGrid::new(64.0);
+        let small = dot(1, Vector2::new(5024.0, -3040.0), 10.0);
+        let huge = dot(2, Vector2::zero(), 1.0e5);
+        let vast = dot(3, Vector2::zero(), 1.0e300);
+        grid.add(small.clone());
+        grid.add(huge.clone());
+        grid.add(vast.clone());
+        assert_eq!(grid.cells.len(), 1);
+        assert_eq!(grid.oversized.len(), 2);
+
+        assert_eq!(sorted_ids(&grid.objects()), vec![1, 2, 3]);
+        assert_eq!(sorted_ids(&grid.query_point(Vector2::new(-9000.0, 9000.0))), vec![2, 3]);
+        assert_eq!(sorted_ids(&grid.query_point(Vector2::new(5024.0, -3040.0))), vec![1, 2, 3]);
+
+        // Rays start inside the huge object, and hit it right away
+        let (hit, _) = grid.raycast(Vector2::new(5024.0, -2000.0), Vector2::new(0.0, -1.0), 100.0).unwrap();
+        assert_eq!(hit.borrow().id, 2);
+
+        grid.test_collisions();
+        let mut touching = small.borrow().touching.clone();
+        touching.sort();
+        assert_eq!(touching, vec![2, 3]);
+        let mut touching = huge.borrow().touching.clone();
+        touching.sort();
+        assert_eq!(touching, vec![1, 3]);
+
+        assert!(grid.remove_by_id(3).is_ok());
+        assert!(grid.remove_by_id(2).is_ok());
+        assert!(grid.oversized.is_empty());
+        assert_eq!(sorted_ids(&grid.objects()), vec![1]);
+    }
+}
@@ -0,0 +1,34 @@
+//! Submodule for collision layers.
+//! Every object belongs to one or more categories, and tells which
+//! categories it wants to collide with. Pairs which don't want each
+//! other are discarded before any collision test.
+
+bitflags! {
+    /// Collision categories an object may belong to.
+    pub struct CollisionLayers: u32 {
+        const NONE    = 0;
+        /// Category for objects which don't set one.
+        const DEFAULT = 1;
+        const PLAYER  = 1 << 1;
+        const ENEMY   = 1 << 2;
+        const RING    = 1 << 3;
+        const SCENERY = 1 << 4;
+        const HAZARD  = 1 << 5;
+        const ALL     = !0;
+    }
+}
+
+/// Whether two objects should be tested for collision. Both must
+/// want to collide with the category of the other.
+/// # Arguments
+/// * `category_a` - Categories of the first object.
+///
+/// * `mask_a` - Categories the first object collides with.
+///
+/// * `category_b` - Categories of the second object.
+///
+/// * `mask_b` - Categories the second object collides with.
+pub fn can_collide(category_a: CollisionLayers, mask_a: CollisionLayers,
+                   category_b: CollisionLayers, mask_b: CollisionLayers) -> bool {
+    mask_a.intersects(category_b) && mask_b.intersects(category_a)
+}
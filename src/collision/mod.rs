@@ -1,4 +1,11 @@
 //! Module for handling collision-related algorithms and structures.
 
 pub mod primitives;
+pub mod layers;
+pub mod sat;
+pub mod broadphase;
 pub mod partitioning;
+pub mod grid;
+pub mod sweep_prune;
+pub mod terrain;
+pub mod sensors;
@@ -3,120 +3,196 @@
 use types::Vector2;
 use std::rc::Rc;
 use std::cell::RefCell;
-use common::objects::{ GameObject, ObjectRef };
-use collision::primitives::{ Collidable, Circle };
+use std::collections::HashMap;
+use common::objects::{ AsGameObject, GameObject, ObjectRef };
+use collision::broadphase::{ Broadphase, CollisionEvents, sweep_fast_objects };
+use collision::primitives::{ Collidable, AABB, Circle, Raycastable, RaycastHit, raycast_slabs };
 
 type QuadtreeNodeCountedRef<T> = Rc<RefCell<QuadtreeNode<T>>>;
 type QuadtreeRemoveResult<T>   = Result<ObjectRef<T>, &'static str>;
 
 #[derive(Clone)]
 #[repr(C)]
-struct QuadtreeNode<T: GameObject> {
+struct QuadtreeNode<T: ?Sized + GameObject> {
     center:   Vector2,
-    halfw:    f64,
+    halfws:   [f64; 2],
     children: Vec<QuadtreeNodeCountedRef<T>>,
     objects:  Vec<ObjectRef<T>>,
+
+    // Region where objects on this node may be, as minimum and
+    // maximum points. May be infinite.
+    region:   (Vector2, Vector2),
+    // Position of the node on a preorder traversal, and its depth
+    order:    usize,
+    depth:    u32,
 }
 
-// TODO: These functions repeat the same algorithm for checking if the
-// boundaries are contained inside the current node! We should DEFINITELY
-// outsource them!
-impl<T: GameObject> QuadtreeNode<T> {
-    fn add(&mut self, object: ObjectRef<T>) {
+impl<T: ?Sized + GameObject> QuadtreeNode<T> {
+    /// Whether a bounding circle lies strictly inside the region
+    /// of this node.
+    fn holds(&self, bounds: &Circle) -> bool {
+        let (min, max) = self.region;
+        bounds.center.x - bounds.radius > min.x && bounds.center.x + bounds.radius < max.x
+            && bounds.center.y - bounds.radius > min.y && bounds.center.y + bounds.radius < max.y
+    }
+
+    /// Yields the child which should hold a bounding circle: the
+    /// one on the quadrant of its center, as long as the circle fits
+    /// in there.
+    fn child_for(&self, bounds: &Circle) -> Option<QuadtreeNodeCountedRef<T>> {
+        if self.children.is_empty() {
+            return None;
+        }
+
         let mut index: usize = 0;
-        let mut straddle = false;
-        let bounds = object.borrow().bounding_circle();
-        // Compute quadrant number [0..4] that the object circle center is in.
-        // If straddling any of the dividing x or y planes, exit directly
-        let delta = bounds.center - self.center;
-
-        // Check for X
-        if delta.x.abs() > bounds.radius {
-            if delta.x > 0.0 { index |= 1; }
-            // Check for Y
-            if delta.y.abs() > bounds.radius {
-                if delta.y > 0.0 { index |= 2; }
-            } else { straddle = true; }
-        } else { straddle = true; }
-
-        if !straddle && !self.children.is_empty() {
+        if bounds.center.x > self.center.x { index |= 1; }
+        if bounds.center.y > self.center.y { index |= 2; }
+
+        let child = &self.children[index];
+        if child.borrow().holds(bounds) { Some(child.clone()) } else { None }
+    }
+
+    /// Adds an object to the deepest node which fully contains it,
+    /// yielding that node.
+    fn add(node: &QuadtreeNodeCountedRef<T>, object: ObjectRef<T>) -> QuadtreeNodeCountedRef<T> {
+        let child = {
+            let bounds = object.borrow().bounding_circle();
+            node.borrow().child_for(&bounds)
+        };
+
+        match child {
             // Fully contained in existing child node! Insert in subtree.
-            //println!("DEBUG@QuadtreeNode::add: descent subtree #{}", index + 1);
-            self.children[index].borrow_mut().add(object);
-        } else {
+            Some(child) => QuadtreeNode::add(&child, object),
             // Straddling or no child node to descend to.
-            // Link object into linked list at this node.
-            //println!("DEBUG@QuadtreeNode::add: add object to quadtree. Reason: {}",
-            //         if self.children.is_empty() { "depth limit" } else { "straddling" });
-            self.objects.push(object);
+            None => {
+                node.borrow_mut().objects.push(object);
+                node.clone()
+            },
         }
     }
 
-    fn remove(&mut self, object: ObjectRef<T>, old_position: Vector2) -> QuadtreeRemoveResult<T> {
-        // Most of this function is like the add function
-        let mut index: usize = 0;
-        let mut straddle = false;
-        let mut bounds = object.borrow().bounding_circle();
-        bounds.center = old_position; // We need to check relative to the old object position
-        let delta = bounds.center - self.center;
-
-        // Check for X
-        if delta.x.abs() > bounds.radius {
-            if delta.x > 0.0 { index |= 1; }
-            // Check for Y
-            if delta.y.abs() > bounds.radius {
-                if delta.y > 0.0 { index |= 2; }
-            } else { straddle = true; }
-        } else { straddle = true; }
-
-        if !straddle && !self.children.is_empty() {
-            // We need to check on child nodes, because it is
-            // fully contained in there!
-            self.children[index].borrow_mut().remove(object, old_position)
-        } else {
-            // It is likely here. If it is here, I mean.
-            let mut contains = false;
-            let mut index    = 0;
-            use ref_eq::ref_eq;
-            for elem in &self.objects {
-                if ref_eq(&*elem, &object) {
-                    contains = true;
-                    break;
+    /// Removes an object stored directly on this node.
+    fn remove_id(&mut self, id: u32) -> Option<ObjectRef<T>> {
+        let index = self.objects.iter().position(|object| object.borrow().get_id() == id)?;
+        Some(self.objects.remove(index))
+    }
+
+    fn raycast(node: &QuadtreeNodeCountedRef<T>, origin: Vector2, dir: Vector2, max_dist: f64,
+               nearest: &mut Option<(ObjectRef<T>, RaycastHit)>) {
+        // Objects on a node may stick out of its square, but never
+        // out of its region, so that is what we check the ray against.
+        let limit = match *nearest {
+            Some((_, ref hit)) => hit.t,
+            None => max_dist,
+        };
+        let node = node.borrow();
+        let (region_min, region_max) = node.region;
+        match raycast_slabs(origin, dir, region_min, region_max) {
+            Some((t, _)) if t <= limit => {},
+            _ => return,
+        }
+
+        for object in &node.objects {
+            let limit = match *nearest {
+                Some((_, ref hit)) => hit.t,
+                None => max_dist,
+            };
+            let bounds = object.borrow().bounding_circle();
+            if let Some(hit) = bounds.raycast(origin, dir, limit) {
+                let closer = match *nearest {
+                    Some((_, ref best)) => hit.t < best.t,
+                    None => true,
+                };
+                if closer {
+                    *nearest = Some((object.clone(), hit));
                 }
-                index += 1;
             }
+        }
 
-            if !contains {
-                Err("Object does not exist in this quadtree")
-            } else {
-                println!("Fst len: {}", self.objects.len());
-                let object = self.objects.remove(index);
-                println!("Snd len: {}", self.objects.len());
-                Ok(object)
+        for child in &node.children {
+            QuadtreeNode::raycast(child, origin, dir, max_dist, nearest);
+        }
+    }
+
+    fn collect<R, K>(node: &QuadtreeNodeCountedRef<T>, in_region: &R, keep: &K,
+                     found: &mut Vec<ObjectRef<T>>)
+        where R: Fn(Vector2, Vector2) -> bool, K: Fn(&Circle) -> bool {
+        // Just like on raycasts, objects never leave their node's region
+        let node = node.borrow();
+        if !in_region(node.region.0, node.region.1) {
+            return;
+        }
+
+        for object in &node.objects {
+            if keep(&object.borrow().bounding_circle()) {
+                found.push(object.clone());
             }
         }
+
+        for child in &node.children {
+            QuadtreeNode::collect(child, in_region, keep, found);
+        }
+    }
+
+    fn nearest(node: &QuadtreeNodeCountedRef<T>, point: Vector2, k: usize,
+               best: &mut Vec<(f64, ObjectRef<T>)>) {
+        // Skip regions farther than everything we already have
+        let node = node.borrow();
+        if best.len() == k && distance_to_region(point, node.region.0, node.region.1) > best[k - 1].0 {
+            return;
+        }
+
+        for object in &node.objects {
+            let bounds = object.borrow().bounding_circle();
+            let distance = ((point - bounds.center).length() - bounds.radius).max(0.0);
+            let position = best.iter().position(|&(other, _)| distance < other).unwrap_or(best.len());
+            if position < k {
+                best.insert(position, (distance, object.clone()));
+                best.truncate(k);
+            }
+        }
+
+        // Closest children first, so that farther ones get skipped
+        let mut children: Vec<(f64, &QuadtreeNodeCountedRef<T>)> = node.children.iter()
+            .map(|child| {
+                let (min, max) = child.borrow().region;
+                (distance_to_region(point, min, max), child)
+            })
+            .collect();
+        children.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        for (_, child) in children {
+            QuadtreeNode::nearest(child, point, k, best);
+        }
+    }
+
+    /// Collects every node whose region overlaps a given one, and
+    /// which doesn't come before a given node on a preorder traversal.
+    fn overlapping(node: &QuadtreeNodeCountedRef<T>, region: (Vector2, Vector2), from: usize,
+                   found: &mut Vec<QuadtreeNodeCountedRef<T>>) {
+        // Regions of children are inside their parent's, so
+        // whole subtrees can be skipped
+        let this = node.borrow();
+        if !regions_overlap(this.region, region) {
+            return;
+        }
+        if this.order >= from {
+            found.push(node.clone());
+        }
+        for child in &this.children {
+            QuadtreeNode::overlapping(child, region, from, found);
+        }
     }
 
     fn local_iter(node: ObjectRef<QuadtreeNode<T>>, bounds: Circle) -> QuadtreeIter<T> {
-        // Like add and remove, check whether the bounding circle
+        // Like add, check whether the bounding circle
         // is contained inside this area. If it is, then it is wise
         // to find a more specific iterator on the subtrees.
-        let mut index: usize = 0;
-        let mut straddle = false;
-        let delta = bounds.center - node.borrow().center;
-
-        // Check for X
-        if delta.x.abs() > bounds.radius {
-            if delta.x > 0.0 { index |= 1; }
-            // Check for Y
-            if delta.y.abs() > bounds.radius {
-                if delta.y > 0.0 { index |= 2; }
-            } else { straddle = true; }
-        } else { straddle = true; }
-
-        if !straddle && !node.borrow().children.is_empty() {
+        let child = node.borrow().child_for(&bounds);
+
+        if let Some(child) = child {
             // We need a more local iterator
-            QuadtreeNode::local_iter(node.borrow().children[index].clone(), bounds)
+            QuadtreeNode::local_iter(child, bounds)
             //self.borrow().children[index].borrow().local_iter(bounds)
         } else {
             // Yup, we need to start here
@@ -135,55 +211,135 @@ impl<T: GameObject> QuadtreeNode<T> {
 /// Implements a quadtree, a special tree which partitions the entire space in
 /// four blocks. Each block is then recursively divided like the former space,
 /// until the tree reaches its depth limit.
-pub struct Quadtree<T: GameObject> {
+///
+/// Space may be rectangular. Whenever an object is added outside of
+/// it, the tree is rebuilt over a larger space which holds it.
+///
+/// Objects which straddle the dividing lines of a node stay on it.
+/// A loose quadtree grows the region of each child node instead,
+/// so that straddling objects can still go down the tree, at the
+/// expense of testing collisions against neighbouring nodes too.
+pub struct Quadtree<T: ?Sized + GameObject> {
     root:      QuadtreeNodeCountedRef<T>,
     max_depth: u32,
+    looseness: Option<f64>,
 
-    // For iteration purposes only!
-    // Must always have len() zero after an iteration.
-    ancestors: Vec<Option<QuadtreeNodeCountedRef<T>>>,
+    // Candidate pairs tested on the last collision pass
+    tested_pairs: usize,
 
     // Schedule for update
-    update_queue: Vec<(ObjectRef<T>, Vector2)>,
+    update_queue: Vec<ObjectRef<T>>,
+
+    // Node where each object lives, by id
+    index: HashMap<u32, QuadtreeNodeCountedRef<T>>,
+
+    events: CollisionEvents<T>,
 }
 
 // Constructor
-impl<T: GameObject> Quadtree<T> {
+impl<T: ?Sized + GameObject> Quadtree<T> {
     /// Creates a new quadtree.
     /// # Arguments
     /// * `center` - Center of space to be partitioned.
     ///
-    /// * `half_width` - Half-width of space to be partitioned. Remember that the quadtree
-    /// assumes a squared space, not a rectangular one.
+    /// * `half_width` - Half-width of space to be partitioned. For
+    /// rectangular spaces, see `with_bounds`.
     ///
     /// * `max-depth` - Maximum depth the quadtree can reach. If no depth is provided,
     /// the tree will only divide the space in four areas. A depth of three should be fine
     /// for simple cases.
     pub fn new(center: Vector2, half_width: f64, max_depth: u32) -> Quadtree<T> {
+        Quadtree::build(center, [half_width, half_width], max_depth, None)
+    }
+
+    /// Creates a new quadtree over a rectangular space.
+    /// # Arguments
+    /// * `bounds` - Space to be partitioned, e.g. the size of the level.
+    ///
+    /// * `max-depth` - Maximum depth the quadtree can reach.
+    pub fn with_bounds(bounds: &AABB, max_depth: u32) -> Quadtree<T> {
+        Quadtree::build(bounds.center, bounds.halfws, max_depth, None)
+    }
+
+    /// Creates a new loose quadtree.
+    /// # Arguments
+    /// * `center` - Center of space to be partitioned.
+    ///
+    /// * `half_width` - Half-width of space to be partitioned.
+    ///
+    /// * `max-depth` - Maximum depth the quadtree can reach.
+    ///
+    /// * `looseness` - How much larger than its square the region of a
+    /// child node is. Must be at least `1.0`; `2.0` is the usual choice.
+    pub fn new_loose(center: Vector2, half_width: f64, max_depth: u32, looseness: f64) -> Quadtree<T> {
+        Quadtree::build(center, [half_width, half_width], max_depth, Some(looseness.max(1.0)))
+    }
+
+    /// Creates a new loose quadtree over a rectangular space.
+    /// # Arguments
+    /// * `bounds` - Space to be partitioned, e.g. the size of the level.
+    ///
+    /// * `max-depth` - Maximum depth the quadtree can reach.
+    ///
+    /// * `looseness` - How much larger than its square the region of a
+    /// child node is. Must be at least `1.0`.
+    pub fn loose_with_bounds(bounds: &AABB, max_depth: u32, looseness: f64) -> Quadtree<T> {
+        Quadtree::build(bounds.center, bounds.halfws, max_depth, Some(looseness.max(1.0)))
+    }
+
+    fn build(center: Vector2, halfws: [f64; 2], max_depth: u32, looseness: Option<f64>) -> Quadtree<T> {
         Quadtree {
-            root: Quadtree::build_tree(center, half_width, max_depth + 1).unwrap(),
+            root: Quadtree::build_root(center, halfws, max_depth, looseness),
             max_depth: max_depth,
-            ancestors: vec![],
+            looseness: looseness,
+            tested_pairs: 0,
             update_queue: vec![],
+            index:        HashMap::new(),
+            events:       CollisionEvents::new(),
         }
     }
+
+    fn build_root(center: Vector2, halfws: [f64; 2], max_depth: u32, looseness: Option<f64>) -> QuadtreeNodeCountedRef<T> {
+        let mut order = 0;
+        Quadtree::build_tree(center, halfws, max_depth + 1, Quadtree::<T>::whole_region(),
+                             looseness, 0, &mut order).unwrap()
+    }
     
-    fn build_tree(center: Vector2, half_width: f64, stop_depth: u32) -> Option<QuadtreeNodeCountedRef<T>> {
+    fn build_tree(center: Vector2, halfws: [f64; 2], stop_depth: u32, region: (Vector2, Vector2),
+                  looseness: Option<f64>, depth: u32, order: &mut usize) -> Option<QuadtreeNodeCountedRef<T>> {
         match stop_depth {
             0 => None,
             // Construct and fill the root of this subtree
             _ => Some(Rc::new(RefCell::new(QuadtreeNode {
                     center:   center,
-                    halfw:    half_width,
+                    halfws:   halfws,
                     objects:  Vec::new(),
+                    region:   region,
+                    order:    { *order += 1; *order - 1 },
+                    depth:    depth,
                     children: {
                         let mut children = vec![];
-                        let step = half_width * 0.5;
+                        let step = [halfws[0] * 0.5, halfws[1] * 0.5];
                         for i in 0..4 {
-                            let offset_x = if i & 1 != 0 { step } else { -step };
-                            let offset_y = if i & 2 != 0 { step } else { -step };
+                            let offset_x = if i & 1 != 0 { step[0] } else { -step[0] };
+                            let offset_y = if i & 2 != 0 { step[1] } else { -step[1] };
                             let offset = Vector2::new(offset_x, offset_y);
-                            if let Some(node) = Quadtree::build_tree(center + offset, step, stop_depth - 1) {
+                            let child_region = match looseness {
+                                // A box larger than the child's
+                                Some(looseness) => {
+                                    let reach = Vector2::new(step[0] * looseness, step[1] * looseness);
+                                    (center + offset - reach, center + offset + reach)
+                                },
+                                // Our region, cut down to the quadrant
+                                None => {
+                                    let (mut min, mut max) = region;
+                                    if i & 1 != 0 { min.x = center.x; } else { max.x = center.x; }
+                                    if i & 2 != 0 { min.y = center.y; } else { max.y = center.y; }
+                                    (min, max)
+                                },
+                            };
+                            if let Some(node) = Quadtree::build_tree(center + offset, step, stop_depth - 1,
+                                                                     child_region, looseness, depth + 1, order) {
                                 children.push(node);
                             }
                         }
@@ -194,6 +350,48 @@ impl<T: GameObject> Quadtree<T> {
         }
     }
 
+    /// Yields the region covered by the root node, which holds
+    /// anything, anywhere.
+    fn whole_region() -> (Vector2, Vector2) {
+        let infinity = ::std::f64::INFINITY;
+        (Vector2::new(-infinity, -infinity), Vector2::new(infinity, infinity))
+    }
+
+    /// Yields the space currently partitioned by the tree.
+    pub fn bounds(&self) -> AABB {
+        let root = self.root.borrow();
+        AABB { center: root.center, halfws: root.halfws }
+    }
+
+    /// Whether this is a loose quadtree.
+    pub fn is_loose(&self) -> bool {
+        self.looseness.is_some()
+    }
+
+    /// Yields statistics about how objects are spread over the tree.
+    pub fn stats(&self) -> QuadtreeStats {
+        let mut stats = QuadtreeStats {
+            nodes:             0,
+            objects:           0,
+            root_objects:      self.root.borrow().objects.len(),
+            max_node_objects:  0,
+            objects_per_depth: vec![0; self.max_depth as usize + 1],
+            tested_pairs:      self.tested_pairs,
+        };
+
+        let mut pending = vec![self.root.clone()];
+        while let Some(node) = pending.pop() {
+            let node = node.borrow();
+            let count = node.objects.len();
+            stats.nodes += 1;
+            stats.objects += count;
+            stats.max_node_objects = stats.max_node_objects.max(count);
+            stats.objects_per_depth[node.depth as usize] += count;
+            pending.extend(node.children.iter().cloned());
+        }
+        stats
+    }
+
     /// Yields an iterator for this quadtree.
     pub fn iter(&self) -> QuadtreeIter<T> {
         QuadtreeIter {
@@ -216,109 +414,249 @@ impl<T: GameObject> Quadtree<T> {
 
 
 // General methods
-impl<T: 'static + GameObject> Quadtree<T> {
-    /// Adds a game object to the quadtree.
-    /// # Arguments
-    /// * `object` - A dynamically-allocated object which should be added to spatial
-    /// partitioning structure.
-    pub fn add(&mut self, object: ObjectRef<T>) {
-        self.root.borrow_mut().add(object);
+impl<T: 'static + ?Sized + GameObject + AsGameObject> Quadtree<T> {
+    fn place(&mut self, object: ObjectRef<T>) {
+        let id = object.borrow().get_id();
+        let node = QuadtreeNode::add(&self.root, object);
+        self.index.insert(id, node);
     }
 
-    /// Removes a game object from the quadtree.
-    /// # Arguments
-    /// * `object` - A reference to the dynamically-allocated object which should be removed
-    ///
-    /// * `old_position` - Object position prior to its change, if changed.
-    pub fn remove(&mut self, object: ObjectRef<T>, old_position: Vector2) -> QuadtreeRemoveResult<T> {
-        self.root.borrow_mut().remove(object, old_position)
+    /// Whether a bounding circle fits in the partitioned space,
+    /// including the padding loose nodes reach past it. Circles
+    /// touching its edges from inside fit. Circles which can't ever
+    /// fit, such as infinite ones, are said to fit anyway, so that
+    /// they don't grow the tree.
+    fn covers(&self, bounds: &Circle) -> bool {
+        if !(bounds.center.x.is_finite() && bounds.center.y.is_finite() && bounds.radius.is_finite()) {
+            return true;
+        }
+
+        // The children of a loose root stick out of its square
+        let space = self.bounds();
+        let padding = match self.looseness {
+            Some(looseness) => (looseness - 1.0) * 0.5,
+            None => 0.0,
+        };
+        let reach = Vector2::new(space.halfws[0] * padding, space.halfws[1] * padding);
+        let (min, max) = (space.min() - reach, space.max() + reach);
+        bounds.center.x - bounds.radius >= min.x && bounds.center.x + bounds.radius <= max.x
+            && bounds.center.y - bounds.radius >= min.y && bounds.center.y + bounds.radius <= max.y
+    }
+
+    /// Rebuilds the tree over a larger space, which holds a bounding
+    /// circle. Space at least doubles along each side the circle is
+    /// out of, so that objects going away don't rebuild it every time.
+    fn grow(&mut self, bounds: &Circle) {
+        let space = self.bounds();
+        let (mut min, mut max) = (space.min(), space.max());
+        let size = max - min;
+        let reach = Vector2::new(bounds.radius, bounds.radius);
+        let (reach_min, reach_max) = (bounds.center - reach, bounds.center + reach);
+
+        if reach_min.x < min.x { min.x = reach_min.x.min(min.x - size.x); }
+        if reach_max.x > max.x { max.x = reach_max.x.max(max.x + size.x); }
+        if reach_min.y < min.y { min.y = reach_min.y.min(min.y - size.y); }
+        if reach_max.y > max.y { max.y = reach_max.y.max(max.y + size.y); }
+
+        let objects: Vec<ObjectRef<T>> = self.iter().collect();
+        self.root = Quadtree::build_root((min + max) * 0.5, [(max.x - min.x) * 0.5, (max.y - min.y) * 0.5],
+                                         self.max_depth, self.looseness);
+        self.index.clear();
+        for object in objects {
+            self.place(object);
+        }
     }
 
-    /// Schedules a game object for positioning update.
+    /// Yields up to `k` objects nearest to a point, nearest first.
+    /// Distances are measured to the edge of the bounding circles.
     /// # Arguments
-    /// * `object` - A reference to the dynamically-allocated object which should be updated
+    /// * `point` - Point to measure distances from.
     ///
-    /// * `old_position` - Object position prior to its change.
-    pub fn schedule_update(&mut self, object: ObjectRef<T>, old_position: Vector2) {
-        self.update_queue.push((object.clone(), old_position));
-    }
-
-    /// Updates pending objects which need repositioning.
-    /// Yields an error string on failure.
-    pub fn update_positions(&mut self) -> Result<(), &str> {
-        let update_queue = self.update_queue.clone();
-        self.update_queue.clear();
-        for pair in update_queue.iter() {
-            self.remove(pair.0.clone(), pair.1)?;
-            self.add(pair.0.clone());
-        }
-        Ok(())
-    }
-
-    /// Tests all collisions between objects, calling
-    /// each object's `on_collision` callback.
-    pub fn test_collisions(&mut self) {
-        self.test_all_collisions(None);
-
-        // "muh asserts on production"
-        // yep, son
-        assert!(self.ancestors.len() == 0);
-    }
-
-    fn test_all_collisions(&mut self, tree: Option<QuadtreeNodeCountedRef<T>>) {
-        // Keep track of ancestor objects
-        self.ancestors.push(tree.clone());
-        
-        for root_a in &self.ancestors {
-            //let root_a = &self.ancestors[n];
-            let root_a = match root_a {
-                &None => self.root.clone(),
-                &Some(ref node) => node.clone(),
-            };
+    /// * `k` - How many objects to yield, at most.
+    pub fn nearest(&self, point: Vector2, k: usize) -> Vec<ObjectRef<T>> {
+        let mut best = vec![];
+        if k > 0 {
+            QuadtreeNode::nearest(&self.root, point, k, &mut best);
+        }
+        best.into_iter().map(|(_, object)| object).collect()
+    }
 
-            let root_b = match &tree {
-                &None => self.root.clone(),
-                &Some(ref node) => node.clone(),
-            };
+    fn collect<R, K>(&self, in_region: &R, keep: &K) -> Vec<ObjectRef<T>>
+        where R: Fn(Vector2, Vector2) -> bool, K: Fn(&Circle) -> bool {
+        let mut found = vec![];
+        QuadtreeNode::collect(&self.root, in_region, keep, &mut found);
+        found
+    }
 
-            // Only traded places between names obj_a and obj_b
-            // to make outputs and comparision order prettier.
-            // Bear with me.
-            for obj_b in root_a.borrow().objects.iter() {
-                for obj_a in root_b.borrow().objects.iter() {
-                    use ref_eq::ref_eq;
-
-                    // Discard same objects
-                    if ref_eq(&*obj_a, &*obj_b) {
-                        break;
-                    } // Does this really work???
- 
-                    // Test collision
-                    let bounding_a = (**obj_a).borrow().bounding_circle();
-                    let bounding_b = (**obj_b).borrow().bounding_circle();
-
-                    if bounding_a.collides(&bounding_b) {
-                        // Trigger collision callbacks and pass along
-                        // a clone of the counted reference
-                        obj_a.borrow_mut().on_collision((*obj_b).clone());
-                        obj_b.borrow_mut().on_collision((*obj_a).clone());
+    fn test_all_collisions(&mut self) {
+        // Objects on a node may only touch objects on nodes with an
+        // overlapping region. On a regular quadtree, those are the
+        // node itself, its ancestors and its descendants.
+        let mut nodes = vec![];
+        QuadtreeNode::overlapping(&self.root, Quadtree::<T>::whole_region(), 0, &mut nodes);
+
+        for node_a in &nodes {
+            let (region, order) = {
+                let node = node_a.borrow();
+                (node.region, node.order)
+            };
+            let mut partners = vec![];
+            QuadtreeNode::overlapping(&self.root, region, order, &mut partners);
+
+            for node_b in partners {
+                let same_node = node_b.borrow().order == order;
+                let objects_a = node_a.borrow().objects.clone();
+                let objects_b = node_b.borrow().objects.clone();
+
+                for (i, obj_a) in objects_a.iter().enumerate() {
+                    // Pairs on the same node are only tested once
+                    let skip = if same_node { i + 1 } else { 0 };
+                    for obj_b in objects_b.iter().skip(skip) {
+                        if self.events.test_pair(obj_a, obj_b) {
+                            self.tested_pairs += 1;
+                        }
                     }
                 }
             }
         }
+    }
+
+    fn test_fast_collisions(&mut self) {
+        let fast: Vec<ObjectRef<T>> = self.iter()
+            .filter(|object| object.borrow().is_fast())
+            .collect();
+
+        let (tested, found) = sweep_fast_objects(&fast, |aabb| self.query_aabb(aabb));
+        self.tested_pairs += tested;
+        for (obj_a, obj_b, contact) in found {
+            self.events.push(obj_a, obj_b, contact);
+        }
+    }
+}
+
+// Broadphase interface
+impl<T: 'static + ?Sized + GameObject + AsGameObject> Broadphase<T> for Quadtree<T> {
+    fn add(&mut self, object: ObjectRef<T>) {
+        let id = object.borrow().get_id();
+        if self.index.contains_key(&id) {
+            let _ = self.remove_by_id(id);
+        }
+
+        let bounds = object.borrow().bounding_circle();
+        if !self.covers(&bounds) {
+            self.grow(&bounds);
+        }
+        self.place(object);
+    }
 
-        let children_root = tree.unwrap_or(self.root.clone());
-        let children = &children_root.borrow().children;
-        for node in children {
-            self.test_all_collisions(Some(node.clone()));
+    /// The node holding the object is looked up by id, but the
+    /// node itself is then scanned for it, so it takes longer on
+    /// crowded nodes.
+    fn remove_by_id(&mut self, id: u32) -> QuadtreeRemoveResult<T> {
+        let node = self.index.remove(&id).ok_or("Object does not exist in this quadtree")?;
+        let object = node.borrow_mut().remove_id(id);
+        object.ok_or("Object does not exist in this quadtree")
+    }
+
+    fn schedule_update(&mut self, object: ObjectRef<T>) {
+        self.update_queue.push(object);
+    }
+
+    fn update_positions(&mut self) -> Result<(), Vec<(u32, &'static str)>> {
+        let update_queue: Vec<_> = self.update_queue.drain(..).collect();
+        let mut failures = vec![];
+        for object in update_queue {
+            match self.remove(object.clone()) {
+                Ok(_) => self.add(object),
+                Err(reason) => failures.push((object.borrow().get_id(), reason)),
+            }
         }
 
-        // Remove current node from ancestor stack before returning
-        let _ = self.ancestors.pop();
+        if failures.is_empty() { Ok(()) } else { Err(failures) }
+    }
+
+    fn test_collisions(&mut self) {
+        self.tested_pairs = 0;
+        self.test_all_collisions();
+        self.test_fast_collisions();
+        self.events.dispatch();
+    }
+
+    fn objects(&self) -> Vec<ObjectRef<T>> {
+        self.iter().collect()
+    }
+
+    fn local_objects(&self, bounds: Circle) -> Vec<ObjectRef<T>> {
+        self.local_iter(bounds).collect()
+    }
+
+    fn query_aabb(&self, aabb: &AABB) -> Vec<ObjectRef<T>> {
+        let (min, max) = (aabb.min(), aabb.max());
+        self.collect(&|region_min, region_max| boxes_overlap(min, max, region_min, region_max),
+                     &|bounds| bounds.collides(aabb))
+    }
+
+    fn query_circle(&self, circle: &Circle) -> Vec<ObjectRef<T>> {
+        let reach = Vector2::new(circle.radius, circle.radius);
+        let (min, max) = (circle.center - reach, circle.center + reach);
+        self.collect(&|region_min, region_max| boxes_overlap(min, max, region_min, region_max),
+                     &|bounds| bounds.collides(circle))
+    }
+
+    fn query_point(&self, point: Vector2) -> Vec<ObjectRef<T>> {
+        self.collect(&|region_min, region_max| boxes_overlap(point, point, region_min, region_max),
+                     &|bounds| (point - bounds.center).length() <= bounds.radius)
+    }
+
+    /// Nodes the ray doesn't go through are skipped.
+    fn raycast(&self, origin: Vector2, dir: Vector2, max_dist: f64) -> Option<(ObjectRef<T>, RaycastHit)> {
+        let dir = dir.normalized();
+        if dir == Vector2::zero() {
+            return None;
+        }
+
+        let mut nearest = None;
+        QuadtreeNode::raycast(&self.root, origin, dir, max_dist, &mut nearest);
+        nearest
     }
 }
 
-struct QuadtreeIterNode<T: GameObject> {
+/// Statistics about a quadtree.
+#[derive(Debug, Clone)]
+pub struct QuadtreeStats {
+    /// How many nodes the tree has.
+    pub nodes:             usize,
+    /// How many objects the tree holds.
+    pub objects:           usize,
+    /// How many objects are held by the root node.
+    pub root_objects:      usize,
+    /// Most objects held by a single node.
+    pub max_node_objects:  usize,
+    /// How many objects are held on each depth, root first.
+    pub objects_per_depth: Vec<usize>,
+    /// How many pairs of objects were tested on the last collision pass.
+    pub tested_pairs:      usize,
+}
+
+/// Whether the interiors of two regions, given as minimum and
+/// maximum points, overlap. Regions may be infinite.
+fn regions_overlap(a: (Vector2, Vector2), b: (Vector2, Vector2)) -> bool {
+    (a.0).x < (b.1).x && (a.1).x > (b.0).x && (a.0).y < (b.1).y && (a.1).y > (b.0).y
+}
+
+/// Whether two boxes, given by their minimum and maximum points,
+/// overlap. Bounds may be infinite.
+fn boxes_overlap(min_a: Vector2, max_a: Vector2, min_b: Vector2, max_b: Vector2) -> bool {
+    min_a.x <= max_b.x && max_a.x >= min_b.x && min_a.y <= max_b.y && max_a.y >= min_b.y
+}
+
+/// Yields the distance from a point to a box. Bounds may be infinite.
+fn distance_to_region(point: Vector2, min: Vector2, max: Vector2) -> f64 {
+    let closest = Vector2::new(point.x.max(min.x).min(max.x), point.y.max(min.y).min(max.y));
+    (point - closest).length()
+}
+
+struct QuadtreeIterNode<T: ?Sized + GameObject> {
     current: u32,
     read:    bool,
     node:    QuadtreeNodeCountedRef<T>,
@@ -327,11 +665,11 @@ struct QuadtreeIterNode<T: GameObject> {
 /// Iterator for Quadtree.
 /// Iterates on all objects on quadtree, though it doesn't guarantee
 /// an iteration order.
-pub struct QuadtreeIter<T: GameObject> {
+pub struct QuadtreeIter<T: ?Sized + GameObject> {
     nodes:   Vec<QuadtreeIterNode<T>>,
 }
 
-impl<T: 'static + GameObject> Iterator for QuadtreeIter<T> {
+impl<T: 'static + ?Sized + GameObject> Iterator for QuadtreeIter<T> {
     type Item = ObjectRef<T>;
 
     fn next(&mut self) -> Option<ObjectRef<T>> {
@@ -368,3 +706,190 @@ impl<T: 'static + GameObject> Iterator for QuadtreeIter<T> {
         self.next()
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::rng::Rng;
+    use collision::broadphase::tests::{ Dot, dot };
+    use test::Bencher;
+
+    fn random_tree(seed: u64) -> (Quadtree<Dot>, Vec<ObjectRef<Dot>>) {
+        fill_tree(Quadtree::new(Vector2::zero(), 400.0, 4), seed)
+    }
+
+    fn fill_tree(mut tree: Quadtree<Dot>, seed: u64) -> (Quadtree<Dot>, Vec<ObjectRef<Dot>>) {
+        let mut rng = Rng::new(seed);
+        let mut dots = vec![];
+        for id in 0..300 {
+            let center = Vector2::new(rng.range(-450.0, 450.0), rng.range(-450.0, 450.0));
+            let dot = dot(id, center, rng.range(1.0, 30.0));
+            tree.add(dot.clone());
+            dots.push(dot);
+        }
+        (tree, dots)
+    }
+
+    /// Fills a tree with objects lying on its horizontal dividing
+    /// line, which a regular quadtree can't take off the root.
+    fn straddling_tree(mut tree: Quadtree<Dot>) -> (Quadtree<Dot>, Vec<ObjectRef<Dot>>) {
+        let mut rng = Rng::new(6);
+        let mut dots = vec![];
+        for id in 0..200 {
+            let dot = dot(id, Vector2::new(rng.range(-350.0, 350.0), 0.0), rng.range(1.0, 30.0));
+            tree.add(dot.clone());
+            dots.push(dot);
+        }
+        (tree, dots)
+    }
+
+    #[test]
+    fn nearest_matches_brute_force() {
+        let loose = Quadtree::new_loose(Vector2::zero(), 400.0, 4, 2.0);
+        let (tree, dots) = random_tree(3);
+        assert_nearest_match(&tree, &dots);
+        let (tree, dots) = fill_tree(loose, 3);
+        assert_nearest_match(&tree, &dots);
+    }
+
+    fn assert_nearest_match(tree: &Quadtree<Dot>, dots: &[ObjectRef<Dot>]) {
+        let mut rng = Rng::new(4);
+        for _ in 0..100 {
+            let point = Vector2::new(rng.range(-500.0, 500.0), rng.range(-500.0, 500.0));
+            let distance = |dot: &ObjectRef<Dot>| {
+                let bounds = &dot.borrow().bounds;
+                ((point - bounds.center).length() - bounds.radius).max(0.0)
+            };
+
+            let mut expected: Vec<f64> = dots.iter().map(&distance).collect();
+            expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            expected.truncate(5);
+
+            let found: Vec<f64> = tree.nearest(point, 5).iter().map(&distance).collect();
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn objects_are_removed_and_relocated_by_id() {
+        let (mut tree, dots) = random_tree(5);
+
+        // Move everything far away without telling the tree
+        for dot in &dots {
+            dot.borrow_mut().bounds.center = Vector2::new(1000.0, -1000.0);
+            tree.schedule_update(dot.clone());
+        }
+        assert!(tree.update_positions().is_ok());
+        assert_eq!(tree.query_point(Vector2::new(1000.0, -1000.0)).len(), dots.len());
+
+        let removed = tree.remove_by_id(7).unwrap();
+        assert_eq!(removed.borrow().id, 7);
+        assert!(tree.remove_by_id(7).is_err());
+
+        // Failures are reported without stopping the others
+        tree.schedule_update(dots[7].clone());
+        tree.schedule_update(dots[8].clone());
+        assert_eq!(tree.update_positions(), Err(vec![(7, "Object does not exist in this quadtree")]));
+        assert_eq!(tree.iter().count(), dots.len() - 1);
+    }
+
+    #[test]
+    fn loose_tree_finds_the_same_collisions() {
+        let (mut tree, dots) = random_tree(7);
+        let loose = Quadtree::new_loose(Vector2::zero(), 400.0, 4, 2.0);
+        let (mut loose, loose_dots) = fill_tree(loose, 7);
+        tree.test_collisions();
+        loose.test_collisions();
+
+        let touching = |dots: &[ObjectRef<Dot>]| -> Vec<Vec<u32>> {
+            dots.iter().map(|dot| {
+                let mut touching = dot.borrow().touching.clone();
+                touching.sort();
+                touching
+            }).collect()
+        };
+        assert_eq!(touching(&loose_dots), touching(&dots));
+        assert_eq!(loose.stats().objects, tree.stats().objects);
+    }
+
+    #[test]
+    fn loose_tree_keeps_straddling_objects_off_the_root() {
+        let (mut tree, _) = straddling_tree(Quadtree::new(Vector2::zero(), 400.0, 4));
+        let (mut loose, _) = straddling_tree(Quadtree::new_loose(Vector2::zero(), 400.0, 4, 2.0));
+        tree.test_collisions();
+        loose.test_collisions();
+
+        let (stats, loose_stats) = (tree.stats(), loose.stats());
+        assert_eq!(stats.root_objects, 200);
+        assert_eq!(stats.tested_pairs, 200 * 199 / 2);
+        assert_eq!(loose_stats.root_objects, 0);
+        assert!(loose_stats.max_node_objects < stats.max_node_objects);
+        assert!(loose_stats.tested_pairs < stats.tested_pairs);
+    }
+
+    #[test]
+    fn tree_grows_to_hold_objects_out_of_bounds() {
+        // A long level, with its top-left corner at the origin
+        let level = AABB { center: Vector2::new(400.0, 100.0), halfws: [400.0, 100.0] };
+        let mut tree = Quadtree::with_bounds(&level, 4);
+        let dot = dot(0, Vector2::new(-50.0, 150.0), 10.0);
+        tree.add(dot.clone());
+
+        // Space doubles towards the object only
+        let space = tree.bounds();
+        assert_eq!((space.min(), space.max()), (Vector2::new(-800.0, 0.0), Vector2::new(800.0, 200.0)));
+        assert_eq!(tree.stats().root_objects, 0);
+
+        // Unless the object went farther than that
+        dot.borrow_mut().bounds.center = Vector2::new(5000.0, -3000.0);
+        tree.schedule_update(dot.clone());
+        assert!(tree.update_positions().is_ok());
+        let space = tree.bounds();
+        assert_eq!((space.min(), space.max()), (Vector2::new(-800.0, -3010.0), Vector2::new(5010.0, 200.0)));
+        assert_eq!(tree.stats().root_objects, 0);
+        assert_eq!(tree.query_point(Vector2::new(5000.0, -3000.0)).len(), 1);
+    }
+
+    #[test]
+    fn objects_touching_the_edge_from_inside_dont_grow_the_tree() {
+        let mut tree = Quadtree::new(Vector2::zero(), 400.0, 4);
+        tree.add(dot(0, Vector2::new(390.0, -390.0), 10.0));
+        let space = tree.bounds();
+        assert_eq!((space.min(), space.max()), (Vector2::new(-400.0, -400.0), Vector2::new(400.0, 400.0)));
+
+        // Loose children reach halfway past the square
+        let mut tree = Quadtree::new_loose(Vector2::zero(), 400.0, 4, 2.0);
+        tree.add(dot(0, Vector2::new(590.0, -590.0), 10.0));
+        let space = tree.bounds();
+        assert_eq!((space.min(), space.max()), (Vector2::new(-400.0, -400.0), Vector2::new(400.0, 400.0)));
+        assert_eq!(tree.query_point(Vector2::new(590.0, -590.0)).len(), 1);
+
+        tree.add(dot(1, Vector2::new(-595.0, 0.0), 10.0));
+        assert!(tree.bounds().min().x < -400.0);
+    }
+
+    #[bench]
+    fn bench_straddling_collisions(b: &mut Bencher) {
+        let (mut tree, _) = straddling_tree(Quadtree::new(Vector2::zero(), 400.0, 4));
+        b.iter(|| tree.test_collisions());
+    }
+
+    #[bench]
+    fn bench_straddling_collisions_loose(b: &mut Bencher) {
+        let (mut tree, _) = straddling_tree(Quadtree::new_loose(Vector2::zero(), 400.0, 4, 2.0));
+        b.iter(|| tree.test_collisions());
+    }
+
+    #[bench]
+    fn bench_scattered_collisions(b: &mut Bencher) {
+        let (mut tree, _) = random_tree(8);
+        b.iter(|| tree.test_collisions());
+    }
+
+    #[bench]
+    fn bench_scattered_collisions_loose(b: &mut Bencher) {
+        let (mut tree, _) = fill_tree(Quadtree::new_loose(Vector2::zero(), 400.0, 4, 2.0), 8);
+        b.iter(|| tree.test_collisions());
+    }
+}
@@ -1,6 +1,8 @@
 //! Submodule related to defining bounding volume primitives.
 
 use types::Vector2;
+use collision::sat;
+use collision::sat::{ SatShape, RoundedPolygon };
 
 
 /// Common trait for all objects which can interact
@@ -13,7 +15,96 @@ pub trait Collidable<T> {
     /// this trait as well.
     fn collides(&self, other: &T) -> bool;
 
-    
+    /// Yields how the object overlaps another one, if it does.
+    /// The contact normal points towards this object, so moving it
+    /// along the normal by the penetration depth separates both.
+    /// # Arguments
+    /// * `other` - Reference to the other object, which impls
+    /// this trait as well.
+    fn contact(&self, other: &T) -> Option<Contact>;
+}
+
+/// Describes how two objects overlap.
+#[derive(Debug, Clone, Copy)]
+pub struct Contact {
+    /// Direction to push this object towards, away from the other.
+    pub normal: Vector2,
+    /// How far the objects overlap along the normal.
+    pub depth:  f64,
+    /// Point where the objects touch.
+    pub point:  Vector2,
+}
+
+impl Contact {
+    /// Yields the same contact, as seen by the other object.
+    pub fn flipped(&self) -> Contact {
+        Contact {
+            normal: self.normal * -1.0,
+            depth:  self.depth,
+            point:  self.point,
+        }
+    }
+}
+
+/// Common trait for all objects which can be swept (moved
+/// continuously) against another one, so that fast objects don't
+/// tunnel through thin ones.
+pub trait Sweepable<T> {
+    /// Moves the object along a displacement, yielding when and
+    /// how it first touches another, static object. Moving objects
+    /// can be tested with their relative displacement.
+    /// # Arguments
+    /// * `velocity` - Displacement of the object during the step.
+    ///
+    /// * `other` - Reference to the other object.
+    fn sweep(&self, velocity: Vector2, other: &T) -> Option<SweepHit>;
+}
+
+/// Describes when and how a swept object hit another.
+#[derive(Debug, Clone, Copy)]
+pub struct SweepHit {
+    /// Time of impact, from `0.0` (start of the step) to `1.0`
+    /// (end of the step). Objects already touching hit at `0.0`.
+    pub toi:    f64,
+    /// Normal of the contact, pointing from the other object
+    /// towards the swept one.
+    pub normal: Vector2,
+}
+
+/// Common trait for all objects which can be hit by rays.
+pub trait Raycastable {
+    /// Casts a ray against the object, yielding where it hits
+    /// first, if it does. A ray starting inside the object hits
+    /// it right at its origin.
+    /// # Arguments
+    /// * `origin` - Point where the ray starts.
+    ///
+    /// * `dir` - Direction of the ray. Does not need to be normalized.
+    ///
+    /// * `max_dist` - How far the ray goes.
+    fn raycast(&self, origin: Vector2, dir: Vector2, max_dist: f64) -> Option<RaycastHit>;
+
+    /// Casts a line segment against the object. Just like a ray
+    /// which ends at the other end of the segment.
+    /// # Arguments
+    /// * `from` - Start of the segment.
+    ///
+    /// * `to` - End of the segment.
+    fn segment_cast(&self, from: Vector2, to: Vector2) -> Option<RaycastHit> {
+        let delta = to - from;
+        self.raycast(from, delta, delta.length())
+    }
+}
+
+/// Describes where a ray hit an object.
+#[derive(Debug, Clone, Copy)]
+pub struct RaycastHit {
+    /// Point where the ray hit.
+    pub point:  Vector2,
+    /// Normal of the surface that was hit.
+    pub normal: Vector2,
+    /// Distance travelled by the ray until the hit.
+    pub t:      f64,
 }
 
 /// Common trait for all objects that can be contained
@@ -63,29 +154,74 @@ impl Delimitable for AABB {
     fn bounding_circle(&self) -> Circle {
         Circle {
             center: self.center,
-            radius: self.halfws[0].max(self.halfws[1]),
+            // Half the diagonal, so that corners are inside too
+            radius: self.halfws[0].hypot(self.halfws[1]),
         }
     }
 }
 
 impl Collidable<AABB> for AABB {
     fn collides(&self, other: &AABB) -> bool {
-        let mut r;
-        
-        r = self.halfws[0] + other.halfws[0];
-        if (self.center.x - other.center.x + r) as u32 > (r + r) as u32 { return false; }
-
-        r = self.halfws[1] + other.halfws[1];
-        if (self.center.y - other.center.y + r) as u32 > (r + r) as u32 { return false; }
-        
+        // Separated on either axis means no overlap. Touching
+        // boxes do collide.
+        if (self.center.x - other.center.x).abs() > self.halfws[0] + other.halfws[0] { return false; }
+        if (self.center.y - other.center.y).abs() > self.halfws[1] + other.halfws[1] { return false; }
+
         true
     }
+
+    fn contact(&self, other: &AABB) -> Option<Contact> {
+        let delta = self.center - other.center;
+        let overlap_x = self.halfws[0] + other.halfws[0] - delta.x.abs();
+        let overlap_y = self.halfws[1] + other.halfws[1] - delta.y.abs();
+        if overlap_x < 0.0 || overlap_y < 0.0 {
+            return None;
+        }
+
+        // Middle of the overlapping region
+        let min_a = self.min();
+        let max_a = self.max();
+        let min_b = other.min();
+        let max_b = other.max();
+        let point = Vector2::new((min_a.x.max(min_b.x) + max_a.x.min(max_b.x)) * 0.5,
+                                 (min_a.y.max(min_b.y) + max_a.y.min(max_b.y)) * 0.5);
+
+        // Separate along the axis with the least overlap
+        let side = |d: f64| if d < 0.0 { -1.0 } else { 1.0 };
+        Some(if overlap_x < overlap_y {
+            Contact { normal: Vector2::new(side(delta.x), 0.0), depth: overlap_x, point: point }
+        } else {
+            Contact { normal: Vector2::new(0.0, side(delta.y)), depth: overlap_y, point: point }
+        })
+    }
 }
 
 impl Collidable<Circle> for AABB {
     fn collides(&self, other: &Circle) -> bool {
         other.collides(self) // Already implemented
     }
+
+    fn contact(&self, other: &Circle) -> Option<Contact> {
+        other.contact(self).map(|contact| contact.flipped())
+    }
+}
+
+impl Raycastable for AABB {
+    fn raycast(&self, origin: Vector2, dir: Vector2, max_dist: f64) -> Option<RaycastHit> {
+        let dir = dir.normalized();
+        if dir == Vector2::zero() {
+            return None;
+        }
+        let (t, normal) = raycast_slabs(origin, dir, self.min(), self.max())?;
+        if t > max_dist {
+            return None;
+        }
+        Some(RaycastHit {
+            point:  origin + dir * t,
+            normal: normal,
+            t:      t,
+        })
+    }
 }
 
 
@@ -116,6 +252,21 @@ impl Collidable<Circle> for Circle {
         let radius_sum = self.radius + other.radius;
         sq_dist <= (radius_sum * radius_sum)
     }
+
+    fn contact(&self, other: &Circle) -> Option<Contact> {
+        let delta = self.center - other.center;
+        let depth = self.radius + other.radius - delta.length();
+        if depth < 0.0 {
+            return None;
+        }
+
+        let normal = separation_normal(delta, Vector2::zero());
+        Some(Contact {
+            normal: normal,
+            depth:  depth,
+            point:  self.center - normal * (self.radius - depth * 0.5),
+        })
+    }
 }
 
 impl Collidable<AABB> for Circle {
@@ -124,10 +275,320 @@ impl Collidable<AABB> for Circle {
         let sq_dist = sqdist_vector2_aabb(&self.center, other);
         sq_dist <= self.radius * self.radius
     }
+
+    fn contact(&self, other: &AABB) -> Option<Contact> {
+        let closest = closest_vector2_aabb(&self.center, other);
+        let delta = self.center - closest;
+
+        if delta != Vector2::zero() {
+            // Center is outside the box
+            let distance = delta.length();
+            if distance > self.radius {
+                return None;
+            }
+            return Some(Contact {
+                normal: delta * (1.0 / distance),
+                depth:  self.radius - distance,
+                point:  closest,
+            });
+        }
+
+        // Center is inside the box, so leave through the nearest side
+        let min = other.min();
+        let max = other.max();
+        let sides = [(self.center.x - min.x, Vector2::new(-1.0,  0.0)),
+                     (max.x - self.center.x, Vector2::new( 1.0,  0.0)),
+                     (self.center.y - min.y, Vector2::new( 0.0, -1.0)),
+                     (max.y - self.center.y, Vector2::new( 0.0,  1.0))];
+        let mut nearest = sides[0];
+        for side in &sides[1..] {
+            if side.0 < nearest.0 {
+                nearest = *side;
+            }
+        }
+
+        let (distance, normal) = nearest;
+        Some(Contact {
+            normal: normal,
+            depth:  self.radius + distance,
+            point:  self.center + normal * distance,
+        })
+    }
+}
+
+impl Raycastable for Circle {
+    fn raycast(&self, origin: Vector2, dir: Vector2, max_dist: f64) -> Option<RaycastHit> {
+        let dir = dir.normalized();
+        if dir == Vector2::zero() {
+            return None;
+        }
+
+        // Solve |origin + t * dir - center| = radius for t
+        let m = origin - self.center;
+        let b = m.dot(dir);
+        let c = m.dot(m) - self.radius * self.radius;
+
+        // Starting outside and pointing away
+        if c > 0.0 && b > 0.0 {
+            return None;
+        }
+
+        let discriminant = b * b - c;
+        if discriminant < 0.0 {
+            return None;
+        }
+
+        let t = -b - discriminant.sqrt();
+        if t > max_dist {
+            return None;
+        }
+
+        // Negative t means we started inside the circle
+        if t < 0.0 {
+            return Some(RaycastHit {
+                point:  origin,
+                normal: dir * -1.0,
+                t:      0.0,
+            });
+        }
+
+        let point = origin + dir * t;
+        Some(RaycastHit {
+            point:  point,
+            normal: (point - self.center).normalized(),
+            t:      t,
+        })
+    }
+}
+
+
+
+
+impl Sweepable<Circle> for Circle {
+    fn sweep(&self, velocity: Vector2, other: &Circle) -> Option<SweepHit> {
+        if self.collides(other) {
+            return Some(SweepHit {
+                toi:    0.0,
+                normal: separation_normal(self.center - other.center, velocity),
+            });
+        }
+
+        // Sweeping a circle against another is the same as casting
+        // a ray against a circle with both radii
+        let distance = velocity.length();
+        let sum = Circle { center: other.center, radius: self.radius + other.radius };
+        let hit = sum.raycast(self.center, velocity, distance)?;
+        Some(SweepHit {
+            toi:    hit.t / distance,
+            normal: hit.normal,
+        })
+    }
+}
+
+impl Sweepable<AABB> for Circle {
+    fn sweep(&self, velocity: Vector2, other: &AABB) -> Option<SweepHit> {
+        if self.collides(other) {
+            let closest = closest_vector2_aabb(&self.center, other);
+            return Some(SweepHit {
+                toi:    0.0,
+                normal: separation_normal(self.center - closest, velocity),
+            });
+        }
+
+        // Cast a ray against the box grown by the radius. If it
+        // hits near a corner, the grown box is actually rounded
+        // there, so check against a circle on the corner instead.
+        let distance = velocity.length();
+        let grown = AABB {
+            center: other.center,
+            halfws: [other.halfws[0] + self.radius, other.halfws[1] + self.radius],
+        };
+        let mut hit = grown.raycast(self.center, velocity, distance)?;
+
+        let min = other.min();
+        let max = other.max();
+        let outside_x = hit.point.x < min.x || hit.point.x > max.x;
+        let outside_y = hit.point.y < min.y || hit.point.y > max.y;
+        if outside_x && outside_y {
+            let corner = Circle {
+                center: Vector2::new(if hit.point.x < min.x { min.x } else { max.x },
+                                     if hit.point.y < min.y { min.y } else { max.y }),
+                radius: self.radius,
+            };
+            hit = corner.raycast(self.center, velocity, distance)?;
+        }
+
+        Some(SweepHit {
+            toi:    hit.t / distance,
+            normal: hit.normal,
+        })
+    }
+}
+
+
+
+// ================================
+
+/// Represents an oriented bounding box: a box which can be rotated.
+/// Stores its center point, its two half-sizes and its rotation.
+#[derive(Debug, Clone)]
+pub struct OBB {
+    pub center:   Vector2,
+    pub halfws:   [f64; 2],
+    /// Rotation of the box, in radians, clockwise on screen.
+    pub rotation: f64,
+}
+
+impl OBB {
+    /// Yields the local X and Y axes of the box.
+    pub fn axes(&self) -> [Vector2; 2] {
+        let (sin, cos) = self.rotation.sin_cos();
+        [Vector2::new(cos, sin), Vector2::new(-sin, cos)]
+    }
+
+    /// Yields the four corners of the box, in order.
+    pub fn corners(&self) -> [Vector2; 4] {
+        let axes = self.axes();
+        let x = axes[0] * self.halfws[0];
+        let y = axes[1] * self.halfws[1];
+        [self.center - x - y,
+         self.center + x - y,
+         self.center + x + y,
+         self.center - x + y]
+    }
+}
+
+impl Delimitable for OBB {
+    fn bounding_circle(&self) -> Circle {
+        Circle {
+            center: self.center,
+            radius: self.halfws[0].hypot(self.halfws[1]),
+        }
+    }
+}
+
+
+// ================================
+
+/// Represents a capsule: a line segment grown by a radius, with
+/// rounded ends.
+#[derive(Debug, Clone)]
+pub struct Capsule {
+    pub start:  Vector2,
+    pub end:    Vector2,
+    pub radius: f64,
+}
+
+impl Delimitable for Capsule {
+    fn bounding_circle(&self) -> Circle {
+        Circle {
+            center: (self.start + self.end) * 0.5,
+            radius: (self.end - self.start).length() * 0.5 + self.radius,
+        }
+    }
+}
+
+
+// ================================
+
+/// Represents a convex polygon.
+/// Stores its vertices, in order. Winding doesn't matter.
+#[derive(Debug, Clone)]
+pub struct ConvexPolygon {
+    pub vertices: Vec<Vector2>,
+}
+
+impl ConvexPolygon {
+    /// Creates a new convex polygon. The vertices must describe
+    /// a convex shape, in order.
+    /// # Arguments
+    /// * `vertices` - Vertices of the polygon.
+    pub fn new(vertices: Vec<Vector2>) -> Self {
+        ConvexPolygon { vertices: vertices }
+    }
+
+    /// Yields the average of the vertices of the polygon.
+    pub fn centroid(&self) -> Vector2 {
+        let sum = self.vertices.iter().fold(Vector2::zero(), |sum, vertex| sum + *vertex);
+        sum * (1.0 / self.vertices.len().max(1) as f64)
+    }
 }
 
+impl Delimitable for ConvexPolygon {
+    fn bounding_circle(&self) -> Circle {
+        let center = self.centroid();
+        Circle {
+            center: center,
+            radius: self.vertices.iter()
+                .map(|vertex| (*vertex - center).length())
+                .fold(0.0, f64::max),
+        }
+    }
+}
 
 
+// ================================
+
+// SAT shapes for every primitive
+
+impl SatShape for AABB {
+    fn sat_shape(&self) -> RoundedPolygon {
+        let min = self.min();
+        let max = self.max();
+        RoundedPolygon {
+            vertices: vec![min, Vector2::new(max.x, min.y), max, Vector2::new(min.x, max.y)],
+            radius:   0.0,
+        }
+    }
+}
+
+impl SatShape for Circle {
+    fn sat_shape(&self) -> RoundedPolygon {
+        RoundedPolygon { vertices: vec![self.center], radius: self.radius }
+    }
+}
+
+impl SatShape for OBB {
+    fn sat_shape(&self) -> RoundedPolygon {
+        RoundedPolygon { vertices: self.corners().to_vec(), radius: 0.0 }
+    }
+}
+
+impl SatShape for Capsule {
+    fn sat_shape(&self) -> RoundedPolygon {
+        RoundedPolygon { vertices: vec![self.start, self.end], radius: self.radius }
+    }
+}
+
+impl SatShape for ConvexPolygon {
+    fn sat_shape(&self) -> RoundedPolygon {
+        RoundedPolygon { vertices: self.vertices.clone(), radius: 0.0 }
+    }
+}
+
+/// Implements `Collidable` for a pair of primitives through SAT.
+macro_rules! impl_sat_collidable {
+    ($a:ty, $($b:ty),+) => {
+        $(
+            impl Collidable<$b> for $a {
+                fn collides(&self, other: &$b) -> bool {
+                    self.contact(other).is_some()
+                }
+
+                fn contact(&self, other: &$b) -> Option<Contact> {
+                    sat::contact(&self.sat_shape(), &other.sat_shape())
+                }
+            }
+        )+
+    }
+}
+
+// AABB and Circle have their own tests against each other
+impl_sat_collidable!(AABB, OBB, Capsule, ConvexPolygon);
+impl_sat_collidable!(Circle, OBB, Capsule, ConvexPolygon);
+impl_sat_collidable!(OBB, AABB, Circle, OBB, Capsule, ConvexPolygon);
+impl_sat_collidable!(Capsule, AABB, Circle, OBB, Capsule, ConvexPolygon);
+impl_sat_collidable!(ConvexPolygon, AABB, Circle, OBB, Capsule, ConvexPolygon);
 
 // ================================
 
@@ -163,3 +624,357 @@ fn sqdist_vector2_aabb(p: &Vector2, b: &AABB) -> f64 {
 
     sq_dist
 }
+
+/// Returns the point on an AABB which is nearest to a given point.
+/// Points inside the box are returned as is.
+/// # Arguments
+/// * `p` - Reference to point
+///
+/// * `b` - Reference to AABB
+fn closest_vector2_aabb(p: &Vector2, b: &AABB) -> Vector2 {
+    let min = b.min();
+    let max = b.max();
+    Vector2::new(p.x.max(min.x).min(max.x),
+                 p.y.max(min.y).min(max.y))
+}
+
+/// Picks a normal to push two overlapping objects apart. Falls
+/// back to going against the movement, and then to going up, when
+/// the objects are exactly on top of each other.
+/// # Arguments
+/// * `delta` - Vector from the other object towards this one.
+///
+/// * `velocity` - Displacement of this object.
+fn separation_normal(delta: Vector2, velocity: Vector2) -> Vector2 {
+    if delta != Vector2::zero() {
+        delta.normalized()
+    } else if velocity != Vector2::zero() {
+        velocity.normalized() * -1.0
+    } else {
+        Vector2::new(0.0, -1.0)
+    }
+}
+
+/// Intersects a ray against the slabs of a box, yielding the
+/// distance where the ray enters the box and the normal of the
+/// entry side. Bounds may be infinite, which makes it useful for
+/// half-planes and quadrants as well.
+/// # Arguments
+/// * `origin` - Point where the ray starts.
+///
+/// * `dir` - Normalized direction of the ray.
+///
+/// * `min` - Minimum point of the box.
+///
+/// * `max` - Maximum point of the box.
+pub fn raycast_slabs(origin: Vector2, dir: Vector2,
+                     min: Vector2, max: Vector2) -> Option<(f64, Vector2)> {
+    let mut t_min = 0.0;
+    let mut t_max = ::std::f64::INFINITY;
+    let mut normal = dir * -1.0; // In case we start inside
+
+    let axes = [(origin.x, dir.x, min.x, max.x, Vector2::new(1.0, 0.0)),
+                (origin.y, dir.y, min.y, max.y, Vector2::new(0.0, 1.0))];
+
+    for &(o, d, lo, hi, axis) in &axes {
+        if d == 0.0 {
+            // Parallel to the slab: must already be within it
+            if o < lo || o > hi {
+                return None;
+            }
+            continue;
+        }
+
+        let inverse = 1.0 / d;
+        let mut t1 = (lo - o) * inverse;
+        let mut t2 = (hi - o) * inverse;
+        let mut side = -1.0;
+        if t1 > t2 {
+            ::std::mem::swap(&mut t1, &mut t2);
+            side = 1.0;
+        }
+
+        if t1 > t_min {
+            t_min = t1;
+            normal = axis * side;
+        }
+        t_max = t_max.min(t2);
+
+        if t_min > t_max {
+            return None;
+        }
+    }
+
+    Some((t_min, normal))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::rng::Rng;
+    use std::f64::consts::PI;
+
+    /// How many random cases each property is checked against.
+    const CASES: usize = 500;
+
+    /// Any primitive, so that every pair can be tested in a loop.
+    #[derive(Debug, Clone)]
+    enum Shape {
+        Aabb(AABB),
+        Circle(Circle),
+        Obb(OBB),
+        Capsule(Capsule),
+        Polygon(ConvexPolygon),
+    }
+
+    fn test_against<T>(a: &Shape, b: &T) -> (bool, Option<Contact>)
+        where AABB: Collidable<T>, Circle: Collidable<T>, OBB: Collidable<T>,
+              Capsule: Collidable<T>, ConvexPolygon: Collidable<T> {
+        match *a {
+            Shape::Aabb(ref a)    => (a.collides(b), a.contact(b)),
+            Shape::Circle(ref a)  => (a.collides(b), a.contact(b)),
+            Shape::Obb(ref a)     => (a.collides(b), a.contact(b)),
+            Shape::Capsule(ref a) => (a.collides(b), a.contact(b)),
+            Shape::Polygon(ref a) => (a.collides(b), a.contact(b)),
+        }
+    }
+
+    impl Shape {
+        fn random(rng: &mut Rng, kind: usize) -> Shape {
+            let center = Vector2::new(rng.range(-30.0, 30.0), rng.range(-30.0, 30.0));
+            match kind {
+                0 => Shape::Aabb(AABB {
+                    center: center,
+                    halfws: [rng.range(0.1, 15.0), rng.range(0.1, 15.0)],
+                }),
+                1 => Shape::Circle(Circle { center: center, radius: rng.range(0.1, 15.0) }),
+                2 => Shape::Obb(OBB {
+                    center:   center,
+                    halfws:   [rng.range(0.1, 15.0), rng.range(0.1, 15.0)],
+                    rotation: rng.range(0.0, 2.0 * PI),
+                }),
+                3 => Shape::Capsule(Capsule {
+                    start:  center,
+                    end:    center + Vector2::new(rng.range(-20.0, 20.0), rng.range(-20.0, 20.0)),
+                    radius: rng.range(0.1, 10.0),
+                }),
+                _ => {
+                    // Points around a circle, in order, are always convex
+                    let count = 3 + (rng.next_u64() % 5) as usize;
+                    let radius = rng.range(1.0, 15.0);
+                    let mut angles: Vec<f64> = (0..count).map(|_| rng.range(0.0, 2.0 * PI)).collect();
+                    angles.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                    Shape::Polygon(ConvexPolygon::new(angles.iter()
+                        .map(|angle| center + Vector2::new(angle.cos(), angle.sin()) * radius)
+                        .collect()))
+                },
+            }
+        }
+
+        fn test(&self, other: &Shape) -> (bool, Option<Contact>) {
+            match *other {
+                Shape::Aabb(ref b)    => test_against(self, b),
+                Shape::Circle(ref b)  => test_against(self, b),
+                Shape::Obb(ref b)     => test_against(self, b),
+                Shape::Capsule(ref b) => test_against(self, b),
+                Shape::Polygon(ref b) => test_against(self, b),
+            }
+        }
+
+        fn contains(&self, p: Vector2) -> bool {
+            match *self {
+                Shape::Aabb(ref s) => {
+                    let (min, max) = (s.min(), s.max());
+                    p.x >= min.x && p.x <= max.x && p.y >= min.y && p.y <= max.y
+                },
+                Shape::Circle(ref s) => (p - s.center).length() <= s.radius,
+                Shape::Obb(ref s) => {
+                    let axes = s.axes();
+                    let d = p - s.center;
+                    d.dot(axes[0]).abs() <= s.halfws[0] && d.dot(axes[1]).abs() <= s.halfws[1]
+                },
+                Shape::Capsule(ref s) => {
+                    (p - sat::closest_point_segment(p, s.start, s.end)).length() <= s.radius
+                },
+                Shape::Polygon(ref s) => {
+                    let count = s.vertices.len();
+                    let crosses: Vec<f64> = (0..count).map(|i| {
+                        let a = s.vertices[i];
+                        let b = s.vertices[(i + 1) % count];
+                        (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+                    }).collect();
+                    crosses.iter().all(|c| *c >= 0.0) || crosses.iter().all(|c| *c <= 0.0)
+                },
+            }
+        }
+
+        fn translated(&self, by: Vector2) -> Shape {
+            match *self {
+                Shape::Aabb(ref s) => Shape::Aabb(AABB { center: s.center + by, halfws: s.halfws }),
+                Shape::Circle(ref s) => Shape::Circle(Circle { center: s.center + by, radius: s.radius }),
+                Shape::Obb(ref s) => Shape::Obb(OBB {
+                    center: s.center + by, halfws: s.halfws, rotation: s.rotation,
+                }),
+                Shape::Capsule(ref s) => Shape::Capsule(Capsule {
+                    start: s.start + by, end: s.end + by, radius: s.radius,
+                }),
+                Shape::Polygon(ref s) => Shape::Polygon(ConvexPolygon::new(
+                    s.vertices.iter().map(|v| *v + by).collect())),
+            }
+        }
+
+        fn bounding_circle(&self) -> Circle {
+            match *self {
+                Shape::Aabb(ref s)    => s.bounding_circle(),
+                Shape::Circle(ref s)  => s.bounding_circle(),
+                Shape::Obb(ref s)     => s.bounding_circle(),
+                Shape::Capsule(ref s) => s.bounding_circle(),
+                Shape::Polygon(ref s) => s.bounding_circle(),
+            }
+        }
+
+        fn sat_shape(&self) -> RoundedPolygon {
+            match *self {
+                Shape::Aabb(ref s)    => s.sat_shape(),
+                Shape::Circle(ref s)  => s.sat_shape(),
+                Shape::Obb(ref s)     => s.sat_shape(),
+                Shape::Capsule(ref s) => s.sat_shape(),
+                Shape::Polygon(ref s) => s.sat_shape(),
+            }
+        }
+    }
+
+    /// Runs a check on random pairs of every combination of primitives.
+    fn for_every_pair<F: FnMut(&Shape, &Shape)>(seed: u64, mut check: F) {
+        let mut rng = Rng::new(seed);
+        for kind_a in 0..5 {
+            for kind_b in 0..5 {
+                for _ in 0..CASES / 5 {
+                    let a = Shape::random(&mut rng, kind_a);
+                    let b = Shape::random(&mut rng, kind_b);
+                    check(&a, &b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn aabb_overlap_handles_negative_and_fractional_boxes() {
+        let a = AABB { center: Vector2::new(-10.5, -3.25), halfws: [0.5, 0.25] };
+        let touching = AABB { center: Vector2::new(-9.75, -3.0), halfws: [0.3, 0.1] };
+        let apart = AABB { center: Vector2::new(-9.6, -3.0), halfws: [0.3, 0.1] };
+
+        assert!(a.collides(&touching));
+        assert!(!a.collides(&apart));
+        assert!(!apart.collides(&a));
+    }
+
+    #[test]
+    fn collisions_are_symmetric() {
+        for_every_pair(1, |a, b| {
+            let (ab, contact_ab) = a.test(b);
+            let (ba, contact_ba) = b.test(a);
+            assert_eq!(ab, ba, "{:?} vs {:?}", a, b);
+            assert_eq!(ab, contact_ab.is_some(), "{:?} vs {:?}", a, b);
+            if let (Some(contact_ab), Some(contact_ba)) = (contact_ab, contact_ba) {
+                assert!((contact_ab.depth - contact_ba.depth).abs() < 1e-6, "{:?} vs {:?}", a, b);
+            }
+        });
+    }
+
+    #[test]
+    fn collisions_agree_with_sampling() {
+        for_every_pair(2, |a, b| {
+            let (collides, _) = a.test(b);
+            let bounds = a.bounding_circle();
+            let steps = 40;
+            let step = bounds.radius * 2.0 / steps as f64;
+            let corner = bounds.center - Vector2::new(bounds.radius, bounds.radius);
+
+            for y in 0..(steps + 1) {
+                for x in 0..(steps + 1) {
+                    let p = corner + Vector2::new(x as f64 * step, y as f64 * step);
+                    if a.contains(p) && b.contains(p) {
+                        assert!(collides, "{:?} and {:?} share {:?}", a, b, p);
+                        return;
+                    }
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn contacts_separate_shapes() {
+        for_every_pair(3, |a, b| {
+            let contact = match a.test(b).1 {
+                Some(contact) => contact,
+                None => return,
+            };
+            assert!(contact.depth >= 0.0);
+            assert!((contact.normal.length() - 1.0).abs() < 1e-9, "{:?} vs {:?}", a, b);
+
+            let pushed = a.translated(contact.normal * (contact.depth + 0.01));
+            assert!(!pushed.test(b).0, "{:?} vs {:?}: {:?}", a, b, contact);
+
+            if contact.depth > 0.02 {
+                let short = a.translated(contact.normal * (contact.depth - 0.01));
+                assert!(short.test(b).0, "{:?} vs {:?}: {:?}", a, b, contact);
+            }
+        });
+    }
+
+    #[test]
+    fn bounding_circles_contain_shapes() {
+        let mut rng = Rng::new(4);
+        for kind in 0..5 {
+            for _ in 0..CASES {
+                let shape = Shape::random(&mut rng, kind);
+                let bounds = shape.bounding_circle();
+                let rounded = shape.sat_shape();
+                for vertex in &rounded.vertices {
+                    let reach = (*vertex - bounds.center).length() + rounded.radius;
+                    assert!(reach <= bounds.radius + 1e-9, "{:?}", shape);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sqdist_matches_brute_force() {
+        let mut rng = Rng::new(5);
+        for _ in 0..CASES {
+            let b = AABB {
+                center: Vector2::new(rng.range(-30.0, 30.0), rng.range(-30.0, 30.0)),
+                halfws: [rng.range(0.1, 15.0), rng.range(0.1, 15.0)],
+            };
+            let p = Vector2::new(rng.range(-60.0, 60.0), rng.range(-60.0, 60.0));
+            let sq_dist = sqdist_vector2_aabb(&p, &b);
+
+            let shape = Shape::Aabb(b.clone());
+            if shape.contains(p) {
+                assert_eq!(sq_dist, 0.0);
+                continue;
+            }
+
+            // Nearest of many points along the border
+            let (min, max) = (b.min(), b.max());
+            let steps = 1000;
+            let mut brute = ::std::f64::INFINITY;
+            for i in 0..(steps + 1) {
+                let t = i as f64 / steps as f64;
+                let x = min.x + (max.x - min.x) * t;
+                let y = min.y + (max.y - min.y) * t;
+                for q in &[Vector2::new(x, min.y), Vector2::new(x, max.y),
+                           Vector2::new(min.x, y), Vector2::new(max.x, y)] {
+                    let d = p - *q;
+                    brute = brute.min(d.dot(d));
+                }
+            }
+
+            assert!(sq_dist > 0.0);
+            assert!(sq_dist <= brute + 1e-9);
+            assert!(brute - sq_dist < 0.1, "{:?} to {:?}: {} vs {}", p, b, sq_dist, brute);
+        }
+    }
+}
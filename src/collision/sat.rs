@@ -0,0 +1,188 @@
+//! Submodule implementing the separating axis theorem (SAT).
+//! Every convex primitive can be described as a convex polygon
+//! grown by a radius: a circle is a single point with a radius, a
+//! capsule is a segment with a radius, and boxes and polygons have
+//! no radius at all. This lets a single test handle every pair.
+
+use types::Vector2;
+use collision::primitives::Contact;
+
+/// Represents a convex polygon, grown by a radius.
+#[derive(Debug, Clone)]
+pub struct RoundedPolygon {
+    /// Vertices of the polygon, in order. May be a single
+    /// point or a segment.
+    pub vertices: Vec<Vector2>,
+    /// How much the polygon is grown on every direction.
+    pub radius:   f64,
+}
+
+/// Common trait for primitives which can be tested through SAT.
+pub trait SatShape {
+    /// Yields the primitive as a rounded polygon.
+    fn sat_shape(&self) -> RoundedPolygon;
+}
+
+impl RoundedPolygon {
+    /// Projects the polygon onto an axis, radius included.
+    /// Yields the minimum and maximum projections.
+    fn project(&self, axis: Vector2) -> (f64, f64) {
+        let mut min = ::std::f64::INFINITY;
+        let mut max = -::std::f64::INFINITY;
+        for vertex in &self.vertices {
+            let projection = vertex.dot(axis);
+            min = min.min(projection);
+            max = max.max(projection);
+        }
+        (min - self.radius, max + self.radius)
+    }
+
+    /// Yields the edges of the polygon, as pairs of vertices.
+    /// A segment has a single edge; a point has none.
+    fn edges(&self) -> Vec<(Vector2, Vector2)> {
+        let count = self.vertices.len();
+        match count {
+            0 | 1 => vec![],
+            2 => vec![(self.vertices[0], self.vertices[1])],
+            _ => (0..count)
+                .map(|i| (self.vertices[i], self.vertices[(i + 1) % count]))
+                .collect(),
+        }
+    }
+
+    /// Yields the point of the polygon's core (radius excluded)
+    /// which is nearest to a given point.
+    fn closest_point(&self, point: Vector2) -> Vector2 {
+        let edges = self.edges();
+        if edges.is_empty() {
+            return self.vertices[0];
+        }
+
+        let mut nearest = edges[0].0;
+        let mut sq_dist = ::std::f64::INFINITY;
+        for &(a, b) in &edges {
+            let candidate = closest_point_segment(point, a, b);
+            let d = point - candidate;
+            if d.dot(d) < sq_dist {
+                sq_dist = d.dot(d);
+                nearest = candidate;
+            }
+        }
+        nearest
+    }
+
+    /// Yields the point of the polygon's core which goes the
+    /// farthest along a direction. Ties, like a whole edge facing
+    /// the direction, yield their middle point.
+    fn support(&self, direction: Vector2) -> Vector2 {
+        let max = self.vertices.iter()
+            .map(|vertex| vertex.dot(direction))
+            .fold(-::std::f64::INFINITY, f64::max);
+
+        let mut sum = Vector2::zero();
+        let mut count = 0.0;
+        for vertex in &self.vertices {
+            if max - vertex.dot(direction) < EPSILON {
+                sum = sum + *vertex;
+                count += 1.0;
+            }
+        }
+        sum * (1.0 / count)
+    }
+}
+
+/// Tolerance for comparing projections.
+const EPSILON: f64 = 1e-9;
+
+/// Tests two rounded polygons for overlap, yielding their contact
+/// from the point of view of the first one, if they do. The
+/// contact point is approximate when whole edges touch.
+/// # Arguments
+/// * `a` - First shape.
+///
+/// * `b` - Second shape.
+pub fn contact(a: &RoundedPolygon, b: &RoundedPolygon) -> Option<Contact> {
+    if a.vertices.is_empty() || b.vertices.is_empty() {
+        return None;
+    }
+
+    // Candidate axes are the edge normals of both shapes, plus the
+    // directions between vertices and the nearest point of the
+    // other shape, which take care of the rounded parts.
+    let mut axes = vec![];
+    for shape in &[a, b] {
+        for (start, end) in shape.edges() {
+            let edge = end - start;
+            axes.push(Vector2::new(-edge.y, edge.x));
+        }
+    }
+    for vertex in &a.vertices {
+        axes.push(*vertex - b.closest_point(*vertex));
+    }
+    for vertex in &b.vertices {
+        axes.push(a.closest_point(*vertex) - *vertex);
+    }
+
+    // Look for the axis of least penetration. Any axis where the
+    // projections don't overlap separates both shapes.
+    let mut best: Option<(f64, Vector2)> = None;
+    for axis in axes {
+        let axis = axis.normalized();
+        if axis == Vector2::zero() {
+            continue;
+        }
+
+        let (min_a, max_a) = a.project(axis);
+        let (min_b, max_b) = b.project(axis);
+
+        // How far A must go along the axis, or against it, to leave B
+        let forward  = max_b - min_a;
+        let backward = max_a - min_b;
+        if forward < 0.0 || backward < 0.0 {
+            return None;
+        }
+
+        let (depth, normal) = if forward < backward { (forward, axis) } else { (backward, axis * -1.0) };
+        let better = match best {
+            Some((best_depth, _)) => depth < best_depth,
+            None => true,
+        };
+        if better {
+            best = Some((depth, normal));
+        }
+    }
+
+    // Two coincident points have no axis at all
+    let (depth, normal) = best.unwrap_or((a.radius + b.radius, Vector2::new(0.0, -1.0)));
+
+    // Deepest point of B into A, halfway through the overlap
+    let point = b.support(normal) + normal * (b.radius - depth * 0.5);
+    Some(Contact {
+        normal: normal,
+        depth:  depth,
+        point:  point,
+    })
+}
+
+/// Yields the point of a segment which is nearest to a given point.
+/// # Arguments
+/// * `p` - Point being tested.
+///
+/// * `a` - Start of the segment.
+///
+/// * `b` - End of the segment.
+pub fn closest_point_segment(p: Vector2, a: Vector2, b: Vector2) -> Vector2 {
+    let ab = b - a;
+    let sq_length = ab.dot(ab);
+    if sq_length == 0.0 {
+        return a;
+    }
+    let t = (p - a).dot(ab) / sq_length;
+    if t <= 0.0 {
+        a
+    } else if t >= 1.0 {
+        b
+    } else {
+        a + ab * t
+    }
+}
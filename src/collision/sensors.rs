@@ -0,0 +1,290 @@
+//! Submodule for sensor-based collision against terrain.
+//! An object casts six sensors from its position: two floor
+//! sensors (A and B), two ceiling sensors (C and D) and two wall
+//! sensors (E and F). All of them rotate with the object's ground
+//! mode, so that it can run on walls and ceilings.
+
+use types::Vector2;
+use collision::terrain::{ Terrain, Direction };
+
+/// Which surface an object is standing on, which also tells how
+/// its sensors are oriented.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GroundMode {
+    Floor,
+    RightWall,
+    Ceiling,
+    LeftWall,
+}
+
+impl GroundMode {
+    /// Yields the ground mode for standing on a surface with a
+    /// given tile angle.
+    pub fn from_angle(angle: u8) -> Self {
+        match angle {
+            0...32 | 224...255 => GroundMode::Floor,
+            33...95            => GroundMode::LeftWall,
+            96...160           => GroundMode::Ceiling,
+            _                  => GroundMode::RightWall,
+        }
+    }
+
+    /// Rotates a vector given relative to the floor mode, so that
+    /// it is relative to this mode instead.
+    pub fn rotate(&self, v: Vector2) -> Vector2 {
+        match *self {
+            GroundMode::Floor     => v,
+            GroundMode::RightWall => Vector2::new(v.y, -v.x),
+            GroundMode::Ceiling   => Vector2::new(-v.x, -v.y),
+            GroundMode::LeftWall  => Vector2::new(-v.y, v.x),
+        }
+    }
+
+    /// Rotates a direction given relative to the floor mode, so that
+    /// it is relative to this mode instead.
+    pub fn rotate_direction(&self, direction: Direction) -> Direction {
+        let v = match direction {
+            Direction::Down  => Vector2::new(0.0, 1.0),
+            Direction::Up    => Vector2::new(0.0, -1.0),
+            Direction::Left  => Vector2::new(-1.0, 0.0),
+            Direction::Right => Vector2::new(1.0, 0.0),
+        };
+        let v = self.rotate(v);
+        if v.y > 0.5 {
+            Direction::Down
+        } else if v.y < -0.5 {
+            Direction::Up
+        } else if v.x < 0.0 {
+            Direction::Left
+        } else {
+            Direction::Right
+        }
+    }
+}
+
+/// Identifies a sensor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SensorId {
+    /// Left floor sensor.
+    A,
+    /// Right floor sensor.
+    B,
+    /// Left ceiling sensor.
+    C,
+    /// Right ceiling sensor.
+    D,
+    /// Left wall sensor.
+    E,
+    /// Right wall sensor.
+    F,
+}
+
+/// Result of casting a sensor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SensorHit {
+    /// Sensor which found the surface.
+    pub sensor:   SensorId,
+    /// Distance to the surface, along the sensor direction.
+    /// Negative when the sensor is inside the terrain.
+    pub distance: f64,
+    /// Angle of the surface that was found.
+    pub angle:    u8,
+}
+
+/// Describes where the sensors of an object are, relative to its
+/// position, when standing on the floor.
+#[derive(Clone, Copy, Debug)]
+pub struct Sensors {
+    /// Horizontal distance of floor and ceiling sensors.
+    pub width_radius:  f64,
+    /// Vertical distance of floor and ceiling sensors.
+    pub height_radius: f64,
+    /// Horizontal distance of wall sensors.
+    pub push_radius:   f64,
+}
+
+impl Sensors {
+    /// Creates a new set of sensors.
+    pub fn new(width_radius: f64, height_radius: f64, push_radius: f64) -> Self {
+        Sensors {
+            width_radius:  width_radius,
+            height_radius: height_radius,
+            push_radius:   push_radius,
+        }
+    }
+
+    /// Yields where a sensor is cast from, relative to the object's
+    /// position, and towards which direction, when on the floor.
+    fn layout(&self, sensor: SensorId) -> (Vector2, Direction) {
+        let (w, h, p) = (self.width_radius, self.height_radius, self.push_radius);
+        match sensor {
+            SensorId::A => (Vector2::new(-w,  h),  Direction::Down),
+            SensorId::B => (Vector2::new( w,  h),  Direction::Down),
+            SensorId::C => (Vector2::new(-w, -h),  Direction::Up),
+            SensorId::D => (Vector2::new( w, -h),  Direction::Up),
+            SensorId::E => (Vector2::new(-p, 0.0), Direction::Left),
+            SensorId::F => (Vector2::new( p, 0.0), Direction::Right),
+        }
+    }
+
+    /// Casts a single sensor.
+    /// # Arguments
+    /// * `terrain` - Terrain to be sensed.
+    ///
+    /// * `position` - Position of the object.
+    ///
+    /// * `mode` - Current ground mode of the object.
+    ///
+    /// * `sensor` - Sensor to be cast.
+    pub fn cast(&self, terrain: &Terrain, position: Vector2,
+                mode: GroundMode, sensor: SensorId) -> Option<SensorHit> {
+        let (offset, direction) = self.layout(sensor);
+        terrain.sense(position + mode.rotate(offset), mode.rotate_direction(direction))
+            .map(|hit| SensorHit {
+                sensor:   sensor,
+                distance: hit.distance,
+                angle:    hit.angle,
+            })
+    }
+
+    /// Casts both floor sensors (A and B), yielding the nearest hit.
+    pub fn floor(&self, terrain: &Terrain, position: Vector2, mode: GroundMode) -> Option<SensorHit> {
+        self.nearest(terrain, position, mode, SensorId::A, SensorId::B)
+    }
+
+    /// Casts both ceiling sensors (C and D), yielding the nearest hit.
+    pub fn ceiling(&self, terrain: &Terrain, position: Vector2, mode: GroundMode) -> Option<SensorHit> {
+        self.nearest(terrain, position, mode, SensorId::C, SensorId::D)
+    }
+
+    /// Casts the left wall sensor (E).
+    pub fn left_wall(&self, terrain: &Terrain, position: Vector2, mode: GroundMode) -> Option<SensorHit> {
+        self.cast(terrain, position, mode, SensorId::E)
+    }
+
+    /// Casts the right wall sensor (F).
+    pub fn right_wall(&self, terrain: &Terrain, position: Vector2, mode: GroundMode) -> Option<SensorHit> {
+        self.cast(terrain, position, mode, SensorId::F)
+    }
+
+    fn nearest(&self, terrain: &Terrain, position: Vector2, mode: GroundMode,
+               first: SensorId, second: SensorId) -> Option<SensorHit> {
+        let first = self.cast(terrain, position, mode, first);
+        let second = self.cast(terrain, position, mode, second);
+        match (first, second) {
+            (Some(a), Some(b)) => Some(if b.distance < a.distance { b } else { a }),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collision::terrain::{ Tile, TilePlacement, Solidity };
+
+    /// A room with a ceiling at y=32, a floor at y=96, walls at x=16
+    /// and x=144, and a block on the floor from x=48 to x=64.
+    fn room() -> Terrain {
+        let mut terrain = Terrain::new(10, 8);
+        let full = terrain.add_tile(Tile::full(Solidity::FULL));
+        {
+            let mut place = |x, y| terrain.set_tile(x, y, Some(TilePlacement::new(full)));
+            for x in 0..10 {
+                place(x, 1);
+                place(x, 6);
+            }
+            for y in 2..6 {
+                place(0, y);
+                place(9, y);
+            }
+            place(3, 5);
+        }
+        terrain
+    }
+
+    fn sensors() -> Sensors {
+        Sensors::new(9.0, 19.0, 10.0)
+    }
+
+    #[test]
+    fn floor_sensors() {
+        let terrain = room();
+        let hit = sensors().floor(&terrain, Vector2::new(30.0, 70.0), GroundMode::Floor).unwrap();
+        assert_eq!(hit.distance, 7.0);
+        assert_eq!(hit.angle, 0);
+
+        // Embedded in the floor
+        let hit = sensors().floor(&terrain, Vector2::new(30.0, 80.0), GroundMode::Floor).unwrap();
+        assert_eq!(hit.distance, -3.0);
+    }
+
+    #[test]
+    fn nearest_floor_sensor_wins() {
+        let terrain = room();
+        let sensors = sensors();
+
+        // Both find the floor at the same distance, so A wins
+        let hit = sensors.floor(&terrain, Vector2::new(30.0, 70.0), GroundMode::Floor).unwrap();
+        assert_eq!(hit.sensor, SensorId::A);
+
+        // B is over the block
+        let hit = sensors.floor(&terrain, Vector2::new(40.0, 70.0), GroundMode::Floor).unwrap();
+        assert_eq!(hit.sensor, SensorId::B);
+        assert_eq!(hit.distance, -9.0);
+
+        // A is over the block
+        let hit = sensors.floor(&terrain, Vector2::new(70.0, 70.0), GroundMode::Floor).unwrap();
+        assert_eq!(hit.sensor, SensorId::A);
+        assert_eq!(hit.distance, -9.0);
+    }
+
+    #[test]
+    fn ceiling_sensors() {
+        let terrain = room();
+        let hit = sensors().ceiling(&terrain, Vector2::new(40.0, 55.0), GroundMode::Floor).unwrap();
+        assert_eq!(hit.sensor, SensorId::C);
+        assert_eq!(hit.distance, 4.0);
+    }
+
+    #[test]
+    fn wall_sensors() {
+        let terrain = room();
+        let sensors = sensors();
+
+        let hit = sensors.left_wall(&terrain, Vector2::new(30.0, 60.0), GroundMode::Floor).unwrap();
+        assert_eq!((hit.sensor, hit.distance), (SensorId::E, 4.0));
+        assert_eq!(sensors.right_wall(&terrain, Vector2::new(30.0, 60.0), GroundMode::Floor), None);
+
+        let hit = sensors.right_wall(&terrain, Vector2::new(130.0, 60.0), GroundMode::Floor).unwrap();
+        assert_eq!((hit.sensor, hit.distance), (SensorId::F, 4.0));
+        assert_eq!(sensors.left_wall(&terrain, Vector2::new(130.0, 60.0), GroundMode::Floor), None);
+    }
+
+    #[test]
+    fn sensors_rotate_with_the_ground_mode() {
+        let terrain = room();
+        let sensors = sensors();
+
+        // Running on the right wall, floor sensors look right
+        let hit = sensors.floor(&terrain, Vector2::new(120.0, 60.0), GroundMode::RightWall).unwrap();
+        assert_eq!((hit.sensor, hit.distance), (SensorId::A, 5.0));
+
+        // Running on the left wall, floor sensors look left
+        let hit = sensors.floor(&terrain, Vector2::new(40.0, 60.0), GroundMode::LeftWall).unwrap();
+        assert_eq!((hit.sensor, hit.distance), (SensorId::A, 5.0));
+
+        // Running on the ceiling, floor sensors look up
+        let hit = sensors.floor(&terrain, Vector2::new(40.0, 55.0), GroundMode::Ceiling).unwrap();
+        assert_eq!((hit.sensor, hit.distance), (SensorId::A, 4.0));
+    }
+
+    #[test]
+    fn ground_modes_from_angles() {
+        assert_eq!(GroundMode::from_angle(0), GroundMode::Floor);
+        assert_eq!(GroundMode::from_angle(0xf0), GroundMode::Floor);
+        assert_eq!(GroundMode::from_angle(0x40), GroundMode::LeftWall);
+        assert_eq!(GroundMode::from_angle(0x80), GroundMode::Ceiling);
+        assert_eq!(GroundMode::from_angle(0xc0), GroundMode::RightWall);
+    }
+}
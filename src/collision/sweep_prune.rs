@@ -0,0 +1,205 @@
+//! Submodule for sweep and prune, which keeps objects sorted by
+//! where they start along the X axis. Only objects which overlap on
+//! that axis are tested against each other, which fits long
+//! horizontal levels well.
+
+use types::Vector2;
+use common::objects::{ AsGameObject, GameObject, ObjectRef };
+use collision::broadphase::{ Broadphase, CollisionEvents, sweep_fast_objects };
+use collision::primitives::{ Collidable, AABB, Circle, Raycastable, RaycastHit };
+
+type SweepRemoveResult<T> = Result<ObjectRef<T>, &'static str>;
+
+/// An object, along with how far its bounding circle spans on the
+/// X axis.
+struct SweepEntry<T: ?Sized + GameObject> {
+    min_x:  f64,
+    max_x:  f64,
+    object: ObjectRef<T>,
+}
+
+impl<T: ?Sized + GameObject> SweepEntry<T> {
+    fn new(object: ObjectRef<T>) -> SweepEntry<T> {
+        let bounds = object.borrow().bounding_circle();
+        SweepEntry {
+            min_x:  bounds.center.x - bounds.radius,
+            max_x:  bounds.center.x + bounds.radius,
+            object: object,
+        }
+    }
+}
+
+/// Implements sweep and prune along the X axis. Like the grid, it
+/// has no bounds.
+pub struct SweepAndPrune<T: ?Sized + GameObject> {
+    // Sorted by where objects start on the X axis
+    entries: Vec<SweepEntry<T>>,
+
+    // Schedule for update
+    update_queue: Vec<ObjectRef<T>>,
+
+    events: CollisionEvents<T>,
+}
+
+// Constructor
+impl<T: ?Sized + GameObject> SweepAndPrune<T> {
+    /// Creates a new, empty sweep and prune.
+    pub fn new() -> SweepAndPrune<T> {
+        SweepAndPrune {
+            entries:      vec![],
+            update_queue: vec![],
+            events:       CollisionEvents::new(),
+        }
+    }
+
+    /// Sorts the entries again after some of them moved.
+    /// Objects barely move between steps, so an insertion sort does.
+    fn sort(&mut self) {
+        for i in 1..self.entries.len() {
+            let mut j = i;
+            while j > 0 && self.entries[j - 1].min_x > self.entries[j].min_x {
+                self.entries.swap(j - 1, j);
+                j -= 1;
+            }
+        }
+    }
+
+    /// Yields the position of an object on the entries, given its id.
+    fn position(&self, id: u32) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.object.borrow().get_id() == id)
+    }
+
+    fn collect<K>(&self, min_x: f64, max_x: f64, keep: &K) -> Vec<ObjectRef<T>>
+        where K: Fn(&Circle) -> bool {
+        // Entries past the end of the span start too late to touch it
+        let mut found = vec![];
+        for entry in self.entries.iter().take_while(|entry| entry.min_x <= max_x) {
+            if entry.max_x >= min_x && keep(&entry.object.borrow().bounding_circle()) {
+                found.push(entry.object.clone());
+            }
+        }
+        found
+    }
+}
+
+impl<T: ?Sized + GameObject> Default for SweepAndPrune<T> {
+    fn default() -> Self {
+        SweepAndPrune::new()
+    }
+}
+
+// Broadphase interface
+impl<T: 'static + ?Sized + GameObject + AsGameObject> Broadphase<T> for SweepAndPrune<T> {
+    fn add(&mut self, object: ObjectRef<T>) {
+        let id = object.borrow().get_id();
+        if let Some(index) = self.position(id) {
+            self.entries.remove(index);
+        }
+
+        let entry = SweepEntry::new(object);
+        let index = self.entries.iter()
+            .position(|other| other.min_x > entry.min_x)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(index, entry);
+    }
+
+    fn remove_by_id(&mut self, id: u32) -> SweepRemoveResult<T> {
+        let index = self.position(id).ok_or("Object does not exist in this sweep and prune")?;
+        Ok(self.entries.remove(index).object)
+    }
+
+    fn schedule_update(&mut self, object: ObjectRef<T>) {
+        self.update_queue.push(object);
+    }
+
+    fn update_positions(&mut self) -> Result<(), Vec<(u32, &'static str)>> {
+        let update_queue: Vec<_> = self.update_queue.drain(..).collect();
+        let mut failures = vec![];
+        for object in update_queue {
+            let id = object.borrow().get_id();
+            match self.position(id) {
+                Some(index) => self.entries[index] = SweepEntry::new(object),
+                None => failures.push((id, "Object does not exist in this sweep and prune")),
+            }
+        }
+        self.sort();
+
+        if failures.is_empty() { Ok(()) } else { Err(failures) }
+    }
+
+    /// Only objects which overlap on the X axis are tested.
+    fn test_collisions(&mut self) {
+        for (i, entry_a) in self.entries.iter().enumerate() {
+            // Entries are sorted, so the ones starting past the end
+            // of this one don't overlap it, and neither do the rest
+            let partners = self.entries[i + 1..].iter()
+                .take_while(|entry_b| entry_b.min_x <= entry_a.max_x);
+            for entry_b in partners {
+                self.events.test_pair(&entry_a.object, &entry_b.object);
+            }
+        }
+
+        let fast: Vec<ObjectRef<T>> = self.objects().into_iter()
+            .filter(|object| object.borrow().is_fast())
+            .collect();
+        let (_, found) = sweep_fast_objects(&fast, |aabb| self.query_aabb(aabb));
+        for (obj_a, obj_b, contact) in found {
+            self.events.push(obj_a, obj_b, contact);
+        }
+
+        self.events.dispatch();
+    }
+
+    /// Objects are sorted by where they start on the X axis.
+    fn objects(&self) -> Vec<ObjectRef<T>> {
+        self.entries.iter().map(|entry| entry.object.clone()).collect()
+    }
+
+    fn local_objects(&self, bounds: Circle) -> Vec<ObjectRef<T>> {
+        self.query_circle(&bounds)
+    }
+
+    fn query_aabb(&self, aabb: &AABB) -> Vec<ObjectRef<T>> {
+        self.collect(aabb.min().x, aabb.max().x, &|bounds| bounds.collides(aabb))
+    }
+
+    fn query_circle(&self, circle: &Circle) -> Vec<ObjectRef<T>> {
+        self.collect(circle.center.x - circle.radius, circle.center.x + circle.radius,
+                     &|bounds| bounds.collides(circle))
+    }
+
+    fn query_point(&self, point: Vector2) -> Vec<ObjectRef<T>> {
+        self.collect(point.x, point.x, &|bounds| (point - bounds.center).length() <= bounds.radius)
+    }
+
+    /// Only objects within reach of the ray on the X axis are tested.
+    fn raycast(&self, origin: Vector2, dir: Vector2, max_dist: f64) -> Option<(ObjectRef<T>, RaycastHit)> {
+        let dir = dir.normalized();
+        if dir == Vector2::zero() {
+            return None;
+        }
+
+        let end_x = if dir.x == 0.0 { origin.x } else { origin.x + dir.x * max_dist };
+        let candidates = self.collect(origin.x.min(end_x), origin.x.max(end_x), &|_| true);
+
+        let mut nearest: Option<(ObjectRef<T>, RaycastHit)> = None;
+        for object in candidates {
+            let limit = match nearest {
+                Some((_, ref hit)) => hit.t,
+                None => max_dist,
+            };
+            let bounds = object.borrow().bounding_circle();
+            if let Some(hit) = bounds.raycast(origin, dir, limit) {
+                let closer = match nearest {
+                    Some((_, ref best)) => hit.t < best.t,
+                    None => true,
+                };
+                if closer {
+                    nearest = Some((object.clone(), hit));
+                }
+            }
+        }
+        nearest
+    }
+}
+
@@ -0,0 +1,424 @@
+//! Submodule for tile-based terrain.
+//! Terrain is a grid of 16x16 tiles. Each tile describes its solid
+//! shape through a height array (one height per column) and a width
+//! array (one width per row), plus the angle of its surface, much like
+//! the classic Sonic The Hedgehog games.
+
+use types::Vector2;
+
+/// Size of a tile, in pixels.
+pub const TILE_SIZE: usize = 16;
+
+bitflags! {
+    /// Which sides of a tile are solid.
+    pub struct Solidity: u8 {
+        const NONE = 0;
+        /// Solid only from the top, like a jump-through platform.
+        const TOP  = 1;
+        /// Solid from the left, right and bottom sides.
+        const LRB  = 1 << 1;
+        const FULL = Self::TOP.bits | Self::LRB.bits;
+    }
+}
+
+/// Represents the shape of a single tile.
+#[derive(Clone, Debug)]
+pub struct Tile {
+    /// Height of the solid part of each column, from the bottom.
+    pub heights:  [u8; TILE_SIZE],
+    /// Width of the solid part of each row (top to bottom). Positive
+    /// widths grow from the right, and negative ones from the left.
+    pub widths:   [i8; TILE_SIZE],
+    /// Angle of the surface, in 256ths of a turn, clockwise (just
+    /// like the classic games). Zero is flat ground.
+    pub angle:    u8,
+    pub solidity: Solidity,
+}
+
+impl Tile {
+    /// Creates a new tile.
+    pub fn new(heights: [u8; TILE_SIZE], widths: [i8; TILE_SIZE],
+               angle: u8, solidity: Solidity) -> Self {
+        Tile {
+            heights:  heights,
+            widths:   widths,
+            angle:    angle,
+            solidity: solidity,
+        }
+    }
+
+    /// Creates a new tile from its height array only. The width
+    /// array is derived from it: each row grows from the side its
+    /// solid part touches, and reaches its farthest solid column.
+    /// Rows touching neither side grow from the nearest one.
+    pub fn from_heights(heights: [u8; TILE_SIZE], angle: u8, solidity: Solidity) -> Self {
+        let mut widths = [0; TILE_SIZE];
+        for (row, width) in widths.iter_mut().enumerate() {
+            let row_height = (TILE_SIZE - row) as u8;
+            let solid = |column: &usize| heights[*column] >= row_height;
+            let first = (0..TILE_SIZE).find(&solid);
+            let last = (0..TILE_SIZE).rev().find(&solid);
+
+            *width = match (first, last) {
+                (Some(first), Some(last)) => {
+                    let from_right = (TILE_SIZE - 1 - last) <= first;
+                    if from_right {
+                        (TILE_SIZE - first) as i8
+                    } else {
+                        -((last + 1) as i8)
+                    }
+                },
+                _ => 0,
+            };
+        }
+        Tile::new(heights, widths, angle, solidity)
+    }
+
+    /// Creates a completely solid, flat tile.
+    pub fn full(solidity: Solidity) -> Self {
+        Tile::from_heights([TILE_SIZE as u8; TILE_SIZE], 0, solidity)
+    }
+}
+
+/// Represents a tile placed on the terrain grid.
+#[derive(Clone, Copy, Debug)]
+pub struct TilePlacement {
+    /// Index of the tile, as yielded by `Terrain::add_tile`.
+    pub tile:   usize,
+    pub flip_x: bool,
+    pub flip_y: bool,
+}
+
+impl TilePlacement {
+    /// Places a tile as is, with no flipping.
+    pub fn new(tile: usize) -> Self {
+        TilePlacement {
+            tile:   tile,
+            flip_x: false,
+            flip_y: false,
+        }
+    }
+}
+
+/// Directions a terrain query can look towards.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Down,
+    Up,
+    Left,
+    Right,
+}
+
+/// Result of a terrain query.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TerrainHit {
+    /// Distance from the point to the surface, along the direction
+    /// of the query. Negative when the point is inside the terrain.
+    pub distance: f64,
+    /// Angle of the surface that was found.
+    pub angle:    u8,
+}
+
+
+/// Represents a grid of tiles, with its top-left corner at the origin.
+#[derive(Clone, Debug)]
+pub struct Terrain {
+    tiles:  Vec<Tile>,
+    width:  usize,
+    height: usize,
+    map:    Vec<Option<TilePlacement>>,
+}
+
+impl Terrain {
+    /// Creates a new, empty terrain.
+    /// # Arguments
+    /// * `width` - Width of the grid, in tiles.
+    ///
+    /// * `height` - Height of the grid, in tiles.
+    pub fn new(width: usize, height: usize) -> Self {
+        Terrain {
+            tiles:  vec![],
+            width:  width,
+            height: height,
+            map:    vec![None; width * height],
+        }
+    }
+
+    /// Yields the size of the terrain, in pixels.
+    pub fn size(&self) -> Vector2 {
+        Vector2::new((self.width * TILE_SIZE) as f64,
+                     (self.height * TILE_SIZE) as f64)
+    }
+
+    /// Adds a tile shape to the terrain, so that it can be placed.
+    /// Yields the index of the tile.
+    pub fn add_tile(&mut self, tile: Tile) -> usize {
+        self.tiles.push(tile);
+        self.tiles.len() - 1
+    }
+
+    /// Places a tile on the grid. Placements outside the grid, or
+    /// referring to unknown tiles, are ignored.
+    /// # Arguments
+    /// * `x` - Column of the grid.
+    ///
+    /// * `y` - Row of the grid.
+    ///
+    /// * `placement` - Tile to be placed, or `None` to clear the cell.
+    pub fn set_tile(&mut self, x: usize, y: usize, placement: Option<TilePlacement>) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        if let Some(ref placement) = placement {
+            if placement.tile >= self.tiles.len() {
+                return;
+            }
+        }
+        self.map[y * self.width + x] = placement;
+    }
+
+    /// Yields the tile placed on a cell of the grid, if any.
+    pub fn tile_at(&self, x: i64, y: i64) -> Option<(&Tile, TilePlacement)> {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return None;
+        }
+        let placement = self.map[y as usize * self.width + x as usize]?;
+        Some((&self.tiles[placement.tile], placement))
+    }
+
+    /// Yields the distance from a point to the surface below it,
+    /// and the angle of that surface.
+    pub fn distance_to_surface(&self, point: Vector2) -> Option<TerrainHit> {
+        self.sense(point, Direction::Down)
+    }
+
+    /// Looks for the nearest surface from a point, towards a
+    /// direction. Only the tile containing the point and its
+    /// neighbours along the direction are checked, so surfaces
+    /// farther than a tile away are not found.
+    /// # Arguments
+    /// * `point` - Point where the query starts.
+    ///
+    /// * `direction` - Direction to look towards.
+    pub fn sense(&self, point: Vector2, direction: Direction) -> Option<TerrainHit> {
+        let vertical = direction == Direction::Down || direction == Direction::Up;
+        let sign: i64 = if direction == Direction::Down || direction == Direction::Right { 1 } else { -1 };
+        let (along, across) = if vertical { (point.y, point.x) } else { (point.x, point.y) };
+
+        let size = TILE_SIZE as i64;
+        let tile = div_floor(along.floor() as i64, size);
+        let across_px = across.floor() as i64;
+        let across_tile = div_floor(across_px, size);
+        let index = (across_px - across_tile * size) as usize;
+
+        let probe = |tile: i64| {
+            let (x, y) = if vertical { (across_tile, tile) } else { (tile, across_tile) };
+            self.depth(x, y, index, direction)
+        };
+
+        // Look past empty tiles (extension), and look back from
+        // completely filled tiles (regression).
+        let (depth, angle) = probe(tile);
+        let (tile, depth, angle) =
+            if depth == 0 {
+                let (next_depth, next_angle) = probe(tile + sign);
+                if next_depth == 0 {
+                    return None;
+                }
+                (tile + sign, next_depth, next_angle)
+            } else if depth == size {
+                let (prev_depth, prev_angle) = probe(tile - sign);
+                if prev_depth > 0 {
+                    (tile - sign, prev_depth, prev_angle)
+                } else {
+                    (tile, depth, angle)
+                }
+            } else {
+                (tile, depth, angle)
+            };
+
+        // Depth is measured from the side of the tile where the
+        // query would leave it
+        let surface =
+            if sign > 0 { (tile + 1) * size - depth } else { tile * size + depth };
+
+        Some(TerrainHit {
+            distance: (surface as f64 - along) * sign as f64,
+            angle:    angle,
+        })
+    }
+
+    /// Yields how far the solid part of a tile reaches, on a given
+    /// column or row, measured from the side where a query towards
+    /// `direction` would leave the tile. Also yields the tile angle.
+    fn depth(&self, x: i64, y: i64, index: usize, direction: Direction) -> (i64, u8) {
+        let (tile, placement) = match self.tile_at(x, y) {
+            Some(pair) => pair,
+            None => return (0, 0),
+        };
+
+        let needed = if direction == Direction::Down { Solidity::TOP } else { Solidity::LRB };
+        if !tile.solidity.intersects(needed) {
+            return (0, 0);
+        }
+
+        let size = TILE_SIZE as i64;
+        let last = TILE_SIZE - 1;
+        let flipped = |flipped: bool| if flipped { last - index } else { index };
+
+        // Heights grow from the bottom, and widths from the side
+        // given by their sign. If the solid part grows from the side
+        // the query enters, the surface is right at that side.
+        let depth = match direction {
+            Direction::Down | Direction::Up => {
+                let height = tile.heights[flipped(placement.flip_x)] as i64;
+                let from_exit = placement.flip_y == (direction == Direction::Up);
+                if from_exit || height == 0 { height } else { size }
+            },
+            Direction::Left | Direction::Right => {
+                let width = tile.widths[flipped(placement.flip_y)] as i64;
+                let from_right = (width > 0) != placement.flip_x;
+                let from_exit = from_right == (direction == Direction::Right);
+                if from_exit || width == 0 { width.abs() } else { size }
+            },
+        };
+
+        (depth, flip_angle(tile.angle, placement.flip_x, placement.flip_y))
+    }
+}
+
+/// Converts a tile angle (256ths of a turn, clockwise) to radians,
+/// counterclockwise.
+pub fn angle_to_radians(angle: u8) -> f64 {
+    0u8.wrapping_sub(angle) as f64 * ::std::f64::consts::PI * 2.0 / 256.0
+}
+
+/// Yields the angle of a surface after flipping its tile.
+fn flip_angle(angle: u8, flip_x: bool, flip_y: bool) -> u8 {
+    let mut angle = angle;
+    if flip_x { angle = 0u8.wrapping_sub(angle); }
+    if flip_y { angle = 128u8.wrapping_sub(angle); }
+    angle
+}
+
+/// Integer division, rounding towards negative infinity.
+fn div_floor(a: i64, b: i64) -> i64 {
+    let quotient = a / b;
+    if (a % b != 0) && ((a < 0) != (b < 0)) { quotient - 1 } else { quotient }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Slope going up to the right, with its surface at 45 degrees.
+    fn slope_up_right() -> Tile {
+        let mut heights = [0; TILE_SIZE];
+        for (column, height) in heights.iter_mut().enumerate() {
+            *height = column as u8 + 1;
+        }
+        Tile::from_heights(heights, 0xe0, Solidity::FULL)
+    }
+
+    /// Slope going up to the left, with its surface at 45 degrees.
+    fn slope_up_left() -> Tile {
+        let mut heights = [0; TILE_SIZE];
+        for (column, height) in heights.iter_mut().enumerate() {
+            *height = (TILE_SIZE - column) as u8;
+        }
+        Tile::from_heights(heights, 0x20, Solidity::FULL)
+    }
+
+    /// Places a single tile on the middle of an otherwise empty
+    /// terrain, covering pixels 16 to 32 on both axes.
+    fn terrain_with(tile: Tile, flip_x: bool, flip_y: bool) -> Terrain {
+        let mut terrain = Terrain::new(3, 3);
+        let tile = terrain.add_tile(tile);
+        terrain.set_tile(1, 1, Some(TilePlacement { tile: tile, flip_x: flip_x, flip_y: flip_y }));
+        terrain
+    }
+
+    fn hit(distance: f64, angle: u8) -> Option<TerrainHit> {
+        Some(TerrainHit { distance: distance, angle: angle })
+    }
+
+    #[test]
+    fn widths_grow_from_the_higher_side() {
+        let right = slope_up_right();
+        let left = slope_up_left();
+        for row in 0..TILE_SIZE - 1 {
+            assert_eq!(right.widths[row], row as i8 + 1);
+            assert_eq!(left.widths[row], -(row as i8 + 1));
+        }
+
+        // Full rows touch both sides
+        assert_eq!(right.widths[TILE_SIZE - 1], TILE_SIZE as i8);
+        assert_eq!(left.widths[TILE_SIZE - 1], TILE_SIZE as i8);
+        assert_eq!(Tile::full(Solidity::FULL).widths, [TILE_SIZE as i8; TILE_SIZE]);
+    }
+
+    #[test]
+    fn flat_tiles() {
+        let terrain = terrain_with(Tile::full(Solidity::FULL), false, false);
+        assert_eq!(terrain.sense(Vector2::new(20.0, 10.0), Direction::Down), hit(6.0, 0));
+        assert_eq!(terrain.sense(Vector2::new(20.0, 40.0), Direction::Up), hit(8.0, 0));
+        assert_eq!(terrain.sense(Vector2::new(10.0, 20.0), Direction::Right), hit(6.0, 0));
+        assert_eq!(terrain.sense(Vector2::new(40.0, 20.0), Direction::Left), hit(8.0, 0));
+
+        // Inside the terrain, distances are negative
+        assert_eq!(terrain.sense(Vector2::new(20.0, 20.0), Direction::Down), hit(-4.0, 0));
+    }
+
+    #[test]
+    fn slopes_going_up_to_the_right() {
+        let terrain = terrain_with(slope_up_right(), false, false);
+
+        // Column 4 is 5 pixels high
+        assert_eq!(terrain.sense(Vector2::new(20.5, 10.0), Direction::Down), hit(17.0, 0xe0));
+        assert_eq!(terrain.depth(1, 1, 4, Direction::Down), (5, 0xe0));
+
+        // Row 4 is solid from column 11 on
+        assert_eq!(terrain.sense(Vector2::new(10.0, 20.5), Direction::Right), hit(17.0, 0xe0));
+        assert_eq!(terrain.sense(Vector2::new(40.0, 20.5), Direction::Left), hit(8.0, 0xe0));
+    }
+
+    #[test]
+    fn slopes_going_up_to_the_left() {
+        let terrain = terrain_with(slope_up_left(), false, false);
+
+        // Column 4 is 12 pixels high
+        assert_eq!(terrain.sense(Vector2::new(20.5, 10.0), Direction::Down), hit(10.0, 0x20));
+
+        // Row 4 is solid up to column 4
+        assert_eq!(terrain.sense(Vector2::new(10.0, 20.5), Direction::Right), hit(6.0, 0x20));
+        assert_eq!(terrain.sense(Vector2::new(40.0, 20.5), Direction::Left), hit(19.0, 0x20));
+        assert_eq!(terrain.depth(1, 1, 4, Direction::Left), (5, 0x20));
+    }
+
+    #[test]
+    fn flipped_tiles() {
+        // Flipping horizontally turns it into a slope going up to the left
+        let terrain = terrain_with(slope_up_right(), true, false);
+        assert_eq!(terrain.sense(Vector2::new(20.5, 10.0), Direction::Down), hit(10.0, 0x20));
+        assert_eq!(terrain.sense(Vector2::new(10.0, 20.5), Direction::Right), hit(6.0, 0x20));
+        assert_eq!(terrain.sense(Vector2::new(40.0, 20.5), Direction::Left), hit(19.0, 0x20));
+
+        // Flipping vertically hangs it from the top
+        let terrain = terrain_with(slope_up_right(), false, true);
+        assert_eq!(terrain.sense(Vector2::new(20.5, 40.0), Direction::Up), hit(19.0, 0xa0));
+        assert_eq!(terrain.sense(Vector2::new(20.5, 10.0), Direction::Down), hit(6.0, 0xa0));
+        assert_eq!(terrain.sense(Vector2::new(10.0, 27.5), Direction::Right), hit(17.0, 0xa0));
+    }
+
+    #[test]
+    fn gaps_dont_hide_solid_sides() {
+        // Solid all around a pit in the middle
+        let mut heights = [TILE_SIZE as u8; TILE_SIZE];
+        for height in &mut heights[6..10] {
+            *height = 0;
+        }
+        let terrain = terrain_with(Tile::from_heights(heights, 0, Solidity::FULL), false, false);
+        assert_eq!(terrain.sense(Vector2::new(10.0, 20.0), Direction::Right), hit(6.0, 0));
+        assert_eq!(terrain.sense(Vector2::new(40.0, 20.0), Direction::Left), hit(8.0, 0));
+    }
+}
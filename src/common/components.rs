@@ -0,0 +1,295 @@
+//! Submodule for game object components.
+//! Components only hold data; the world runs the systems in
+//! `common::systems` over them. Objects made only of components can
+//! be built with `ComposedObject`, without writing a new type.
+
+use types::Vector2;
+use collision::primitives::{ Circle, Contact };
+use collision::layers::CollisionLayers;
+use common::objects::{ GameComponent, GameObject, GameObjectRef };
+use render::imaging::{ self, TextureAtlas };
+use game::world::World;
+
+/// Where an object is.
+#[derive(Clone, Debug)]
+pub struct Transform {
+    pub position:     Vector2,
+    /// How much the object moved on the current update.
+    pub displacement: Vector2,
+}
+
+impl Transform {
+    /// Creates a transform at a given position.
+    /// # Arguments
+    /// * `position` - Initial position.
+    pub fn at(position: Vector2) -> Transform {
+        Transform {
+            position:     position,
+            displacement: Vector2::zero(),
+        }
+    }
+
+    /// Moves the transform, keeping track of the displacement.
+    /// # Arguments
+    /// * `offset` - How much to move.
+    pub fn translate(&mut self, offset: Vector2) {
+        self.position = self.position + offset;
+        self.displacement = self.displacement + offset;
+    }
+}
+
+/// How an object looks when it isn't animated.
+#[derive(Clone)]
+pub enum Sprite {
+    /// A filled circle.
+    Circle { color: String, radius: f64 },
+    /// A single frame of a texture atlas.
+    Frame { atlas: TextureAtlas, frame: u32 },
+}
+
+impl Sprite {
+    /// Creates a sprite drawn as a filled circle.
+    /// # Arguments
+    /// * `color` - Color of the circle.
+    ///
+    /// * `radius` - Radius of the circle.
+    pub fn circle(color: &str, radius: f64) -> Sprite {
+        Sprite::Circle { color: String::from(color), radius: radius }
+    }
+}
+
+/// Animation of an object, drawn from a texture atlas.
+#[derive(Clone)]
+pub struct Animator {
+    pub atlas:    TextureAtlas,
+    pub animator: imaging::Animator,
+}
+
+impl Animator {
+    /// Creates an animator component.
+    /// # Arguments
+    /// * `atlas` - Atlas where the frames are taken from.
+    ///
+    /// * `animator` - Animator holding the registered animations.
+    pub fn new(atlas: TextureAtlas, animator: imaging::Animator) -> Animator {
+        Animator {
+            atlas:    atlas,
+            animator: animator,
+        }
+    }
+}
+
+/// Makes an object take part in collisions, through a bounding
+/// circle around its transform.
+#[derive(Clone, Debug)]
+pub struct Collider {
+    pub radius:        f64,
+    pub category:      CollisionLayers,
+    pub collides_with: CollisionLayers,
+    /// Whether collisions should be swept; see `GameObject::is_fast`.
+    pub fast:          bool,
+    /// How many objects are touching this one.
+    pub touching:      u32,
+}
+
+impl Collider {
+    /// Creates a collider on the default category, which collides
+    /// with everything.
+    /// # Arguments
+    /// * `radius` - Radius of the bounding circle.
+    pub fn new(radius: f64) -> Collider {
+        Collider {
+            radius:        radius,
+            category:      CollisionLayers::DEFAULT,
+            collides_with: CollisionLayers::ALL,
+            fast:          false,
+            touching:      0,
+        }
+    }
+
+    /// Yields the same collider, on other collision layers.
+    /// # Arguments
+    /// * `category` - Categories the object belongs to.
+    ///
+    /// * `collides_with` - Categories the object collides with.
+    pub fn layers(mut self, category: CollisionLayers, collides_with: CollisionLayers) -> Collider {
+        self.category = category;
+        self.collides_with = collides_with;
+        self
+    }
+}
+
+/// Makes an object move on its own. Speeds are given in pixels per
+/// second, and accelerations in pixels per second squared.
+#[derive(Clone, Debug)]
+pub struct PhysicsBody {
+    pub velocity:     Vector2,
+    pub acceleration: Vector2,
+}
+
+impl PhysicsBody {
+    /// Creates a body with a starting velocity and no acceleration.
+    /// # Arguments
+    /// * `velocity` - Starting velocity.
+    pub fn new(velocity: Vector2) -> PhysicsBody {
+        PhysicsBody {
+            velocity:     velocity,
+            acceleration: Vector2::zero(),
+        }
+    }
+}
+
+/// The components attached to an object. Each kind of component
+/// may be attached at most once.
+#[derive(Clone, Default)]
+pub struct Components {
+    pub transform: Option<Transform>,
+    pub sprite:    Option<Sprite>,
+    pub animator:  Option<Animator>,
+    pub collider:  Option<Collider>,
+    pub body:      Option<PhysicsBody>,
+}
+
+impl Components {
+    /// Creates an empty set of components.
+    pub fn new() -> Components {
+        Components::default()
+    }
+
+    /// Attaches a component, replacing the one of the same kind.
+    /// # Arguments
+    /// * `component` - Component to be attached.
+    pub fn attach<C: GameComponent>(&mut self, component: C) {
+        component.attach_to(self);
+    }
+
+    /// Yields the position of the transform, or the origin when
+    /// there is none.
+    pub fn position(&self) -> Vector2 {
+        match self.transform {
+            Some(ref transform) => transform.position,
+            None => Vector2::zero(),
+        }
+    }
+}
+
+impl GameComponent for Transform {
+    fn attach_to(self, components: &mut Components) {
+        components.transform = Some(self);
+    }
+}
+
+impl GameComponent for Sprite {
+    fn attach_to(self, components: &mut Components) {
+        components.sprite = Some(self);
+    }
+}
+
+impl GameComponent for Animator {
+    fn attach_to(self, components: &mut Components) {
+        components.animator = Some(self);
+    }
+}
+
+impl GameComponent for Collider {
+    fn attach_to(self, components: &mut Components) {
+        components.collider = Some(self);
+    }
+}
+
+impl GameComponent for PhysicsBody {
+    fn attach_to(self, components: &mut Components) {
+        components.body = Some(self);
+    }
+}
+
+/// A game object made only of components. Everything it does comes
+/// from the systems run over them.
+pub struct ComposedObject {
+    id:         u32,
+    components: Components,
+}
+
+impl ComposedObject {
+    /// Creates an object with no components.
+    /// # Arguments
+    /// * `id` - Number identifying the object.
+    pub fn new(id: u32) -> ComposedObject {
+        ComposedObject {
+            id:         id,
+            components: Components::new(),
+        }
+    }
+
+    /// Yields the same object, with one more component attached.
+    /// # Arguments
+    /// * `component` - Component to be attached.
+    pub fn with<C: GameComponent>(mut self, component: C) -> ComposedObject {
+        self.components.attach(component);
+        self
+    }
+}
+
+impl GameObject for ComposedObject {
+    fn update(&mut self, _dt: f64, _world: &World) { }
+
+    fn components(&mut self) -> Option<&mut Components> {
+        Some(&mut self.components)
+    }
+
+    fn bounding_circle(&self) -> Circle {
+        let radius = match self.components.collider {
+            Some(ref collider) => collider.radius,
+            None => 0.0,
+        };
+        Circle { center: self.components.position(), radius: radius }
+    }
+
+    fn get_position(&self) -> Vector2 {
+        self.components.position()
+    }
+
+    fn get_id(&self) -> u32 {
+        self.id
+    }
+
+    fn category(&self) -> CollisionLayers {
+        match self.components.collider {
+            Some(ref collider) => collider.category,
+            None => CollisionLayers::NONE,
+        }
+    }
+
+    fn collides_with(&self) -> CollisionLayers {
+        match self.components.collider {
+            Some(ref collider) => collider.collides_with,
+            None => CollisionLayers::NONE,
+        }
+    }
+
+    fn is_fast(&self) -> bool {
+        match self.components.collider {
+            Some(ref collider) => collider.fast,
+            None => false,
+        }
+    }
+
+    fn displacement(&self) -> Vector2 {
+        match self.components.transform {
+            Some(ref transform) => transform.displacement,
+            None => Vector2::zero(),
+        }
+    }
+
+    fn on_collision_enter(&mut self, _other: GameObjectRef, _contact: Contact) {
+        if let Some(ref mut collider) = self.components.collider {
+            collider.touching += 1;
+        }
+    }
+
+    fn on_collision_exit(&mut self, _other: GameObjectRef) {
+        if let Some(ref mut collider) = self.components.collider {
+            collider.touching = collider.touching.saturating_sub(1);
+        }
+    }
+}
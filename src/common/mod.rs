@@ -1,7 +1,13 @@
 //! Module for common operations and objects.
 
+pub mod components;
 pub mod objects;
+pub mod rng;
+pub mod systems;
+pub mod timestep;
 
+// Browser builds have no stdout, so print to the console instead.
+#[cfg(target_arch = "wasm32")]
 macro_rules! println {
     ($fmt:expr) => (
         js!(console.log(@{format!(concat!($fmt, "\n"))}))
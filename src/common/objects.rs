@@ -1,8 +1,11 @@
 //! Submodule for common game objects.
 
 use types::Vector2;
-use collision::primitives::Circle;
+use collision::primitives::{ Circle, Contact };
+use collision::layers::CollisionLayers;
+use common::components::Components;
 use render::Renderer2D;
+use game::world::World;
 
 use std::rc::Rc;
 use std::cell::RefCell;
@@ -13,8 +16,14 @@ pub type GameObjectRef = Rc<RefCell<GameObject>>;
 /// Represents a dynamic reference to any object.
 pub type ObjectRef<T> = Rc<RefCell<T>>;
 
-/// Common trait to a game object component.
+/// Common trait to a game object component. See
+/// `common::components` for the available ones.
 pub trait GameComponent {
+    /// Attaches this component to a set of components, replacing
+    /// the one of the same kind, if any.
+    /// # Arguments
+    /// * `components` - Components to attach to.
+    fn attach_to(self, components: &mut Components) where Self: Sized;
 }
 
 /// Common trait to a game object.
@@ -29,7 +38,10 @@ pub trait GameObject {
     /// * `dt` - Time difference between the last frame
     /// and the current frame, in seconds. The value is
     /// likely lower than 1.0.
-    fn update(&mut self, dt: f64);
+    ///
+    /// * `world` - World this object lives in. Use it to read
+    /// input and other shared state.
+    fn update(&mut self, dt: f64, world: &World);
 
     /// Performs on-screen drawing routines for
     /// this game object.
@@ -37,6 +49,11 @@ pub trait GameObject {
     /// * `_renderer` - Reference to the renderer used.
     fn draw(&mut self, _renderer: &Renderer2D) { }
 
+    /// Yields the components this object is made of, if any. The
+    /// world runs its systems over them after updating the object,
+    /// and draws them after drawing it.
+    fn components(&mut self) -> Option<&mut Components> { None }
+
     /// Yields the bounding circle of this object,
     /// used on partitioning and collision operations.
     ///
@@ -50,14 +67,72 @@ pub trait GameObject {
     /// Yields a number identifying this object's instance.
     fn get_id(&self) -> u32;
 
+    /// Yields the collision categories this object belongs to.
+    fn category(&self) -> CollisionLayers { CollisionLayers::DEFAULT }
+
+    /// Yields the collision categories this object collides with.
+    /// Pairs are only tested when both objects want each other.
+    fn collides_with(&self) -> CollisionLayers { CollisionLayers::ALL }
+
+    /// Whether this object moves fast enough to go through others
+    /// in a single step. Fast objects have their collisions swept
+    /// along their last displacement, instead of only checked for
+    /// overlap.
+    fn is_fast(&self) -> bool { false }
+
+    /// Yields how much this object moved on its last update.
+    /// Only needed by fast objects.
+    fn displacement(&self) -> Vector2 { Vector2::zero() }
+
     /// Collision callback for whenever two objects' bounding circles
-    /// overlap. This callback is called once per
-    /// object on collision event, and collisions are never
-    /// repeated (e.g. a collision between A and B is the same as
-    /// a collision between B and A).
+    /// start overlapping, and their collision layers allow it. Called
+    /// once per object, on the first collision pass the objects
+    /// overlap. Pairs are told apart by object id, so ids should be
+    /// unique.
+    /// # Arguments
+    /// * `_other`- Dynamic reference to the other game object.
+    ///
+    /// * `_contact` - How the objects overlap, as seen by this
+    /// object. Move along its normal to get out of the other one.
+    fn on_collision_enter(&mut self, _other: ObjectRef<GameObject>, _contact: Contact) { }
+
+    /// Collision callback for every collision pass after the first
+    /// one where two objects keep overlapping.
     /// # Arguments
-    /// * `other`- Dynamic reference to the other game object.
-    fn on_collision(&mut self, other: ObjectRef<GameObject>);
+    /// * `_other`- Dynamic reference to the other game object.
+    ///
+    /// * `_contact` - How the objects overlap, as seen by this object.
+    fn on_collision_stay(&mut self, _other: ObjectRef<GameObject>, _contact: Contact) { }
+
+    /// Collision callback for the first collision pass where two
+    /// objects which overlapped don't overlap anymore.
+    /// # Arguments
+    /// * `_other`- Dynamic reference to the other game object.
+    fn on_collision_exit(&mut self, _other: ObjectRef<GameObject>) { }
+}
+
+/// Common trait to anything whose references can be turned into
+/// dynamic references to a `GameObject`. Implemented for every
+/// game object type, and for `GameObject` itself, so that
+/// structures holding either of them can pass objects along to
+/// collision callbacks.
+pub trait AsGameObject {
+    /// Yields a dynamic reference to the same object.
+    /// # Arguments
+    /// * `object` - Reference to the object.
+    fn as_game_object(object: &ObjectRef<Self>) -> GameObjectRef;
+}
+
+impl<T: 'static + GameObject> AsGameObject for T {
+    fn as_game_object(object: &ObjectRef<T>) -> GameObjectRef {
+        object.clone()
+    }
+}
+
+impl AsGameObject for GameObject {
+    fn as_game_object(object: &GameObjectRef) -> GameObjectRef {
+        object.clone()
+    }
 }
 
 /// Wraps an object into a dynamic reference.
@@ -0,0 +1,109 @@
+//! Submodule for deterministic random number generation.
+
+/// Represents a seedable pseudo-random number generator
+/// (xorshift64*). The same seed always yields the same sequence,
+/// on every platform, so that levels and replays can be reproduced.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    seed:  u64,
+    state: u64,
+}
+
+impl Rng {
+    /// Creates a new generator.
+    /// # Arguments
+    /// * `seed` - Seed for the generator. Any value is valid, zero included.
+    pub fn new(seed: u64) -> Self {
+        Rng {
+            seed:  seed,
+            state: Rng::scramble(seed),
+        }
+    }
+
+    /// Yields the seed this generator was created with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Restarts the generator with a new seed.
+    /// # Arguments
+    /// * `seed` - New seed for the generator.
+    pub fn reseed(&mut self, seed: u64) {
+        *self = Rng::new(seed);
+    }
+
+    /// Yields the next random 64-bit number.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    /// Yields a random number in the range `[0.0, 1.0)`.
+    pub fn next_f64(&mut self) -> f64 {
+        // Use the upper 53 bits, which is all the precision
+        // a f64 mantissa can take.
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Yields a random number in the range `[min, max)`.
+    /// # Arguments
+    /// * `min` - Lower bound, inclusive.
+    ///
+    /// * `max` - Upper bound, exclusive.
+    pub fn range(&mut self, min: f64, max: f64) -> f64 {
+        min + (self.next_f64() * (max - min))
+    }
+
+    /// Spreads the bits of a seed so that similar seeds yield
+    /// unrelated sequences. Xorshift can't have a zero state,
+    /// so this never yields zero.
+    fn scramble(seed: u64) -> u64 {
+        // SplitMix64 finalizer
+        let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        if z == 0 { 0x9e3779b97f4a7c15 } else { z }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_yields_same_sequence() {
+        let mut first = Rng::new(1234);
+        let mut second = Rng::new(1234);
+        for _ in 0..1000 {
+            assert_eq!(first.next_u64(), second.next_u64());
+        }
+
+        // Reseeding starts the sequence over
+        let expected = Rng::new(99).next_u64();
+        first.reseed(99);
+        assert_eq!(first.next_u64(), expected);
+        assert_eq!(first.seed(), 99);
+    }
+
+    #[test]
+    fn different_seeds_yield_different_sequences() {
+        let mut first = Rng::new(0);
+        let mut second = Rng::new(1);
+        let first: Vec<u64> = (0..8).map(|_| first.next_u64()).collect();
+        let second: Vec<u64> = (0..8).map(|_| second.next_u64()).collect();
+        assert!(first != second);
+    }
+
+    #[test]
+    fn ranges_stay_within_bounds() {
+        let mut rng = Rng::new(7);
+        for _ in 0..1000 {
+            let value = rng.range(-3.0, 5.0);
+            assert!(value >= -3.0, "{} is below the range", value);
+            assert!(value < 5.0, "{} is past the range", value);
+        }
+    }
+}
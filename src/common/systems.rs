@@ -0,0 +1,118 @@
+//! Submodule for the systems run over game object components.
+//! On every update, the world runs them on each object with
+//! components, in this order:
+//!
+//! 1. Transforms start the step with no displacement, right before
+//!    the object's own update, so that it may move them too;
+//! 2. Right after it, physics bodies move their transforms;
+//! 3. Animators advance their animations.
+//!
+//! When drawing, sprites are drawn first, and animations over them.
+
+use types::Vector2;
+use common::components::{ Components, Sprite };
+use render::Renderer2D;
+
+/// Runs the systems which start an update over a set of
+/// components, before the object's own update.
+/// # Arguments
+/// * `components` - Components of a single object.
+pub fn begin_update(components: &mut Components) {
+    reset_displacement(components);
+}
+
+/// Runs every other update system over a set of components, after
+/// the object's own update.
+/// # Arguments
+/// * `components` - Components of a single object.
+///
+/// * `dt` - Time difference between the last frame and the current
+/// frame, in seconds.
+pub fn update(components: &mut Components, dt: f64) {
+    integrate_body(components, dt);
+    advance_animation(components, dt);
+}
+
+/// Runs every drawing system over a set of components.
+/// # Arguments
+/// * `components` - Components of a single object.
+///
+/// * `renderer` - Reference to the renderer used.
+pub fn draw(components: &Components, renderer: &Renderer2D) {
+    draw_sprite(components, renderer);
+    draw_animation(components, renderer);
+}
+
+fn reset_displacement(components: &mut Components) {
+    if let Some(ref mut transform) = components.transform {
+        transform.displacement = Vector2::zero();
+    }
+}
+
+fn integrate_body(components: &mut Components, dt: f64) {
+    // Bodies need somewhere to move
+    if let (Some(body), Some(transform)) = (components.body.as_mut(), components.transform.as_mut()) {
+        body.velocity = body.velocity + body.acceleration * dt;
+        transform.translate(body.velocity * dt);
+    }
+}
+
+fn advance_animation(components: &mut Components, dt: f64) {
+    if let Some(ref mut animator) = components.animator {
+        animator.animator.update(dt);
+    }
+}
+
+fn draw_sprite(components: &Components, renderer: &Renderer2D) {
+    let position = components.position();
+    match components.sprite {
+        Some(Sprite::Circle { ref color, radius }) =>
+            renderer.draw_circle_rel(color, position, radius),
+        Some(Sprite::Frame { ref atlas, frame }) =>
+            atlas.draw_frame(renderer, position, frame),
+        None => {},
+    }
+}
+
+fn draw_animation(components: &Components, renderer: &Renderer2D) {
+    if let Some(ref animator) = components.animator {
+        animator.animator.draw(renderer, components.position(), &animator.atlas);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::components::{ PhysicsBody, Transform };
+
+    #[test]
+    fn bodies_move_their_transforms() {
+        let mut components = Components::new();
+        components.attach(Transform::at(Vector2::new(10.0, 0.0)));
+        components.attach(PhysicsBody {
+            velocity:     Vector2::new(60.0, 0.0),
+            acceleration: Vector2::new(0.0, 120.0),
+        });
+
+        for _ in 0..2 {
+            begin_update(&mut components);
+            update(&mut components, 0.5);
+        }
+
+        // Velocity changes before moving the transform
+        let transform = components.transform.unwrap();
+        assert_eq!(transform.position, Vector2::new(70.0, 90.0));
+        assert_eq!(transform.displacement, Vector2::new(30.0, 60.0));
+    }
+
+    #[test]
+    fn bodies_without_transforms_stay_put() {
+        let mut components = Components::new();
+        components.attach(PhysicsBody::new(Vector2::new(60.0, 0.0)));
+
+        update(&mut components, 1.0);
+
+        assert_eq!(components.position(), Vector2::zero());
+        assert_eq!(components.body.unwrap().velocity, Vector2::new(60.0, 0.0));
+    }
+}
@@ -0,0 +1,108 @@
+//! Submodule for fixed-timestep game loops.
+
+/// Logic steps per second.
+pub const UPDATE_RATE: f64 = 60.0;
+
+/// Maximum amount of logic steps to catch up on a single frame.
+pub const MAX_CATCH_UP_STEPS: u32 = 5;
+
+/// Splits arbitrary frame times into fixed-size logic steps.
+/// Leftover time is kept for the next frame, and can be used to
+/// interpolate rendering between the last two logic states.
+#[derive(Clone, Copy, Debug)]
+pub struct FixedTimestep {
+    step:        f64,
+    max_steps:   u32,
+    accumulator: f64,
+}
+
+impl FixedTimestep {
+    /// Creates a new fixed timestep.
+    /// # Arguments
+    /// * `step` - Duration of a single logic step, in seconds.
+    ///
+    /// * `max_steps` - Maximum amount of steps to be run on a single
+    /// frame. Time exceeding this (e.g. after switching browser tabs)
+    /// is simply dropped, so the game never tries to catch up on it.
+    pub fn new(step: f64, max_steps: u32) -> Self {
+        FixedTimestep {
+            step:        step,
+            max_steps:   max_steps,
+            accumulator: 0.0,
+        }
+    }
+
+    /// Yields the duration of a single logic step, in seconds.
+    pub fn step(&self) -> f64 {
+        self.step
+    }
+
+    /// Feeds the time elapsed since last frame, and yields how many
+    /// logic steps should be run on this frame.
+    /// # Arguments
+    /// * `frame_time` - Time elapsed since the last frame, in seconds.
+    pub fn advance(&mut self, frame_time: f64) -> u32 {
+        let max_time = self.step * self.max_steps as f64;
+        self.accumulator += frame_time.max(0.0).min(max_time);
+
+        let steps = ((self.accumulator / self.step).floor() as u32).min(self.max_steps);
+        self.accumulator -= steps as f64 * self.step;
+        steps
+    }
+
+    /// Yields how far, from `0.0` to `1.0`, we are between the last
+    /// logic step and the next one.
+    pub fn alpha(&self) -> f64 {
+        (self.accumulator / self.step).min(1.0)
+    }
+}
+
+impl Default for FixedTimestep {
+    /// Yields the timestep the game runs at: `UPDATE_RATE` steps
+    /// per second, catching up on at most `MAX_CATCH_UP_STEPS`.
+    fn default() -> Self {
+        FixedTimestep::new(1.0 / UPDATE_RATE, MAX_CATCH_UP_STEPS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::rng::Rng;
+
+    #[test]
+    fn long_frames_are_capped() {
+        let mut timestep = FixedTimestep::default();
+        assert_eq!(timestep.advance(10.0), MAX_CATCH_UP_STEPS);
+
+        // The dropped time is not caught up on later
+        assert_eq!(timestep.advance(0.0), 0);
+        assert!(timestep.alpha() < 1.0);
+
+        // Nor does going back in time undo steps
+        assert_eq!(timestep.advance(-1.0), 0);
+    }
+
+    #[test]
+    fn alpha_stays_below_one() {
+        let mut timestep = FixedTimestep::default();
+        let mut rng = Rng::new(3);
+        for _ in 0..10000 {
+            timestep.advance(rng.range(0.0, 0.2));
+            let alpha = timestep.alpha();
+            assert!(alpha >= 0.0, "alpha went negative: {}", alpha);
+            assert!(alpha < 1.0, "alpha reached one: {}", alpha);
+        }
+    }
+
+    #[test]
+    fn step_count_does_not_depend_on_frame_rate() {
+        // Ten seconds, at every frame rate
+        for &rate in &[30.0, 60.0, 144.0] {
+            let mut timestep = FixedTimestep::default();
+            let frames = (rate * 10.0) as u32;
+            let steps: u32 = (0..frames).map(|_| timestep.advance(1.0 / rate)).sum();
+            assert!((steps as i64 - 600).abs() <= 1, "{} steps at {} Hz", steps, rate);
+        }
+    }
+}
@@ -1,4 +1,5 @@
 //! Module for actual in-game objects. Most of these might not be documented.
 
 pub mod objects;
+pub mod player;
 pub mod world;
@@ -1,15 +1,94 @@
 use types::Vector2;
-use collision::primitives::Circle;
+use collision::primitives::{ Circle, Contact };
+use collision::layers::CollisionLayers;
 use common::objects::{ GameObject, GameObjectRef };
 use render::Renderer2D;
-use stdweb::unstable::TryInto;
 use render::imaging::*;
+use common::rng::Rng;
+use game::world::World;
+use game::player::{ PlayerController, PlayerConstants };
+
+/// The player character, driven by the gamepad.
+pub struct Player {
+    id:           u32,
+    position:     Vector2,
+    displacement: Vector2,
+
+    atlas:        TextureAtlas,
+    animator:     Animator,
+    controller:   PlayerController,
+}
+
+impl Player {
+    pub fn new(id: u32, position: Vector2) -> Player {
+        let mut animator = Animator::new();
+        animator.register("stopped", 0.125,
+                          &[ 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                             0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3, 3, 4, 4 ]);
+
+        animator.register("walking", 0.125, &[ 5, 6, 7, 8, 9, 10 ]);
+        animator.set_animation("stopped");
 
-#[derive(Debug)]
-pub enum EntityType {
-    Player,
+        Player {
+            id:           id,
+            position:     position,
+            displacement: Vector2::zero(),
+
+            atlas:        TextureAtlas::from("sonic.png", Vector2::new(60.0, 60.0)),
+            animator:     animator,
+            controller:   PlayerController::new(PlayerConstants::default()),
+        }
+    }
 }
 
+impl GameObject for Player {
+    fn update(&mut self, dt: f64, world: &World) {
+        let old_position = self.position;
+
+        self.controller.update(&mut self.position, &world.input.borrow().gamepad,
+                               &world.terrain(), dt);
+        self.animator.set_animation(if self.controller.ground_speed() != 0.0 {
+            "walking"
+        } else {
+            "stopped"
+        });
+
+        self.displacement = self.position - old_position;
+        self.animator.update(dt);
+    }
+
+    fn draw(&mut self, renderer: &Renderer2D) {
+        self.animator.draw(renderer, self.position, &self.atlas);
+    }
+
+    fn bounding_circle(&self) -> Circle {
+        Circle { center: self.position, radius: 20.0 }
+    }
+
+    fn get_position(&self) -> Vector2 {
+        self.position
+    }
+
+    fn get_id(&self) -> u32 {
+        self.id
+    }
+
+    fn category(&self) -> CollisionLayers {
+        CollisionLayers::PLAYER
+    }
+
+    fn is_fast(&self) -> bool {
+        // The player can easily outrun its own radius
+        true
+    }
+
+    fn displacement(&self) -> Vector2 {
+        self.displacement
+    }
+}
+
+/// A circle floating around where it was placed, which changes
+/// color while touching the player.
 pub struct Entity {
     id:       u32,
     position: Vector2,
@@ -17,110 +96,43 @@ pub struct Entity {
     radius:   f64,
 
     counter:  f64,
-    collided: (u32, u32),
-    change_color: bool,
-    original:   Vector2,
+    touching: u32,
+    original: Vector2,
     speed:    f64,
-
-    atlas:    Option<TextureAtlas>,
-    animator: Option<Animator>,
 }
 
 impl Entity {
-    pub fn new(id: u32, position: Vector2, color: &str) -> Entity {
-        let mut entity = Entity {
-            id: id,
+    pub fn new(id: u32, position: Vector2, color: &str, rng: &mut Rng) -> Entity {
+        Entity {
+            id:       id,
             position: position,
-            color:    if id == 0 { String::from("#112233") } else { String::from(color) },
-            radius:   if id == 0 { 20.0 } else { 50.0 },
+            color:    String::from(color),
+            radius:   50.0,
             counter:  0.0,
-            collided: (0, 0),
-            change_color: false,
+            touching: 0,
             original: position,
-            speed:    js! { return 1.0 + (Math.random() * 10.0); }.try_into().unwrap(),
-
-            atlas:    None,
-            animator: None,
-        };
-
-        if id == 0 {
-            entity.atlas    = Some(TextureAtlas::from("sonic.png",
-                                                      Vector2::new(60.0, 60.0)));
-            let mut animator    = Animator::new();
-            animator.register("stopped", 0.125,
-                              &[ 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                                 0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3, 3, 4, 4 ]);
-
-            animator.register("walking", 0.125, &[ 5, 6, 7, 8, 9, 10 ]);
-            
-            animator.set_animation("walking");
-            entity.animator = Some(animator.clone());
+            speed:    rng.range(1.0, 11.0),
         }
-
-        entity
     }
 }
 
 impl GameObject for Entity {
-    fn update(&mut self, dt: f64) {
-        if self.id == 0 {
-            // Small circle moves rapidly
-            self.counter = ((self.counter as u32 +
-                             (120.0 * dt) as u32)
-                            % 360) as f64;
-
-            // Uhhh some random path I don't know how to describe
-            //let distance = 250.0 * (self.counter * 2.0).to_radians().sin();
-            //self.position.x = 250.0 + (distance * self.counter.to_radians().cos());
-            //self.position.y = 250.0 + (distance * self.counter.to_radians().sin());
-
-            // Ellipse
-            self.position.x = 250.0 + 30.0 * 5.0 * self.counter.to_radians().cos();
-            //self.position.y = 250.0 + 20.0 * 5.0 * self.counter.to_radians().sin();
-        } else {
-            // Bigger circles move gracefully
-            self.counter = ((self.counter as u32 + (120.0 * dt) as u32) % 1440) as f64;
-            
-            let distance = (self.counter / 4.0).to_radians();
-            self.position.x = self.original.x + 20.0 * distance.sin()
-                * if self.id % 2 == 0 { -1.0 } else { 1.0 } * self.speed;
-            self.position.y = self.original.y + 20.0 * distance.cos()
-                * if self.id % 2 == 0 { 1.0 } else { -1.0 } * self.speed;
-            self.change_color = self.collided.0 != 0;
-        }
-
-        self.collided.1 = self.collided.0;
-        self.collided.0 = 0;
-
-        // Update animator
-        match self.animator {
-            Some(ref mut animator) => animator.update(dt),
-            _ => {}
-        }
+    fn update(&mut self, dt: f64, _world: &World) {
+        // Bigger circles move gracefully
+        self.counter = ((self.counter as u32 + (120.0 * dt) as u32) % 1440) as f64;
+
+        let distance = (self.counter / 4.0).to_radians();
+        self.position.x = self.original.x + 20.0 * distance.sin()
+            * if self.id % 2 == 0 { -1.0 } else { 1.0 } * self.speed;
+        self.position.y = self.original.y + 20.0 * distance.cos()
+            * if self.id % 2 == 0 { 1.0 } else { -1.0 } * self.speed;
     }
 
     fn draw(&mut self, renderer: &Renderer2D) {
-        match self.animator {
-            Some(ref mut animator) => {
-
-                match self.atlas {
-                    Some(ref atlas) => {
-                        animator.draw(renderer, self.position, &atlas);
-                        //atlas.draw_all(renderer, self.position);
-                        //atlas.draw_frame(renderer, self.position, 0);
-                    },
-                    None => {println!("Oops"); },
-                }
-            },
-            None => {
-                renderer.draw_circle_rel(
-                    if self.id != 0 && self.change_color {
-                        "#1122337f"
-                    } else { self.color.as_ref() },
-                    self.position,
-                    self.radius);
-            }
-        }
+        renderer.draw_circle_rel(
+            if self.touching > 0 { "#1122337f" } else { self.color.as_ref() },
+            self.position,
+            self.radius);
     }
 
     fn bounding_circle(&self) -> Circle {
@@ -135,9 +147,16 @@ impl GameObject for Entity {
         self.id
     }
 
-    fn on_collision(&mut self, other: GameObjectRef) {
-        if other.borrow().get_id() == 0 {
-            self.collided.0 += 1;
-        }
+    fn collides_with(&self) -> CollisionLayers {
+        // Circles only care about the player
+        CollisionLayers::PLAYER
+    }
+
+    fn on_collision_enter(&mut self, _other: GameObjectRef, _contact: Contact) {
+        self.touching += 1;
+    }
+
+    fn on_collision_exit(&mut self, _other: GameObjectRef) {
+        self.touching = self.touching.saturating_sub(1);
     }
 }
@@ -0,0 +1,440 @@
+//! Submodule for the player's movement.
+//! Modelled after the classic Sonic The Hedgehog games: all speeds
+//! are given in pixels per frame, and accelerations in pixels per
+//! frame squared, assuming 60 frames per second.
+
+use types::Vector2;
+use input::{ GamepadState, GamepadButton };
+use collision::terrain::{ Terrain, angle_to_radians };
+use collision::sensors::{ Sensors, GroundMode };
+
+/// Frames per second the movement constants were tuned for.
+const BASE_FRAME_RATE: f64 = 60.0;
+
+/// How far a stick must be tilted to count as a direction.
+const STICK_THRESHOLD: f64 = 0.5;
+
+/// Farthest the floor can be for the player to snap to it,
+/// regardless of speed.
+const MAX_SNAP_DISTANCE: f64 = 14.0;
+
+/// Tuning constants for the player controller.
+#[derive(Clone, Debug)]
+pub struct PlayerConstants {
+    /// Ground acceleration when holding a direction.
+    pub acceleration:     f64,
+    /// Ground deceleration when holding against the movement.
+    pub deceleration:     f64,
+    /// Ground deceleration when not holding anything.
+    pub friction:         f64,
+    /// How much slopes pull the player down.
+    pub slope_factor:     f64,
+    /// Ground speed under which the player falls off walls and ceilings.
+    pub fall_off_speed:   f64,
+    /// Maximum speed reachable by running.
+    pub top_speed:        f64,
+    /// Acceleration when holding a direction on air.
+    pub air_acceleration: f64,
+    /// Fraction of horizontal speed lost per frame on air, while
+    /// going up slowly.
+    pub air_drag:         f64,
+    /// Downwards acceleration on air.
+    pub gravity:          f64,
+    /// Maximum falling speed.
+    pub max_fall_speed:   f64,
+    /// Upwards speed given by a jump.
+    pub jump_force:       f64,
+    /// Upwards speed a jump is cut to when the button is released early.
+    pub jump_release:     f64,
+    /// Where the player senses terrain from.
+    pub sensors:          Sensors,
+}
+
+impl Default for PlayerConstants {
+    fn default() -> Self {
+        PlayerConstants {
+            acceleration:     0.046875,
+            deceleration:     0.5,
+            friction:         0.046875,
+            slope_factor:     0.125,
+            fall_off_speed:   2.5,
+            top_speed:        6.0,
+            air_acceleration: 0.09375,
+            air_drag:         0.03125,
+            gravity:          0.21875,
+            max_fall_speed:   16.0,
+            jump_force:       6.5,
+            jump_release:     4.0,
+            sensors:          Sensors::new(9.0, 19.0, 10.0),
+        }
+    }
+}
+
+
+/// Platformer controller for the player.
+#[derive(Clone, Debug)]
+pub struct PlayerController {
+    pub constants: PlayerConstants,
+    ground_speed:  f64,
+    velocity:      Vector2,
+    angle:         u8,
+    mode:          GroundMode,
+    grounded:      bool,
+    jumping:       bool,
+}
+
+impl PlayerController {
+    /// Creates a new controller, standing still on the air.
+    /// # Arguments
+    /// * `constants` - Tuning constants for the controller.
+    pub fn new(constants: PlayerConstants) -> Self {
+        PlayerController {
+            constants:    constants,
+            ground_speed: 0.0,
+            velocity:     Vector2::zero(),
+            angle:        0,
+            mode:         GroundMode::Floor,
+            grounded:     false,
+            jumping:      false,
+        }
+    }
+
+    /// Yields the speed along the ground.
+    pub fn ground_speed(&self) -> f64 {
+        self.ground_speed
+    }
+
+    /// Yields the current velocity, in pixels per frame.
+    pub fn velocity(&self) -> Vector2 {
+        self.velocity
+    }
+
+    /// Whether the player is standing on the ground.
+    pub fn is_grounded(&self) -> bool {
+        self.grounded
+    }
+
+    /// Yields the angle of the ground below the player.
+    pub fn angle(&self) -> u8 {
+        self.angle
+    }
+
+    /// Yields which surface the player is standing on.
+    pub fn mode(&self) -> GroundMode {
+        self.mode
+    }
+
+    /// Runs the controller for a logic step, moving the player.
+    /// # Arguments
+    /// * `position` - Position of the player, which will be changed.
+    ///
+    /// * `gamepad` - Input driving the player.
+    ///
+    /// * `terrain` - Terrain the player moves on.
+    ///
+    /// * `dt` - Duration of the step, in seconds.
+    pub fn update(&mut self, position: &mut Vector2, gamepad: &GamepadState,
+                  terrain: &Terrain, dt: f64) {
+        let frames = dt * BASE_FRAME_RATE;
+        let left  = gamepad.button_pressed(GamepadButton::D_LFT)
+            || gamepad.left_stick().x < -STICK_THRESHOLD;
+        let right = gamepad.button_pressed(GamepadButton::D_RGT)
+            || gamepad.left_stick().x > STICK_THRESHOLD;
+        let direction = if left == right { 0.0 } else if left { -1.0 } else { 1.0 };
+
+        if self.grounded && gamepad.just_pressed(GamepadButton::A) {
+            // Jump away from the surface we're standing on
+            let theta = angle_to_radians(self.angle);
+            self.velocity.x -= self.constants.jump_force * theta.sin();
+            self.velocity.y -= self.constants.jump_force * theta.cos();
+            self.detach();
+            self.jumping = true;
+        }
+
+        if self.grounded {
+            self.update_ground(direction, frames);
+            *position = *position + self.velocity * frames;
+            self.push_walls(position, terrain);
+            self.stick_to_ground(position, terrain);
+        } else {
+            self.update_air(direction, gamepad.button_pressed(GamepadButton::A), frames);
+            *position = *position + self.velocity * frames;
+            self.push_walls(position, terrain);
+            self.collide_air(position, terrain);
+        }
+    }
+
+    fn update_ground(&mut self, direction: f64, frames: f64) {
+        let c = &self.constants;
+        let theta = angle_to_radians(self.angle);
+
+        // Slopes pull us down
+        let speed = self.ground_speed - c.slope_factor * theta.sin() * frames;
+
+        self.ground_speed =
+            if direction == 0.0 {
+                // Friction, until we stop
+                speed - speed.signum() * speed.abs().min(c.friction * frames)
+            } else if speed * direction < 0.0 {
+                // Holding against the movement. Turning around is
+                // instantaneous once the speed crosses zero.
+                let braked = speed + direction * c.deceleration * frames;
+                if braked * direction > 0.0 { direction * c.deceleration } else { braked }
+            } else if speed.abs() < c.top_speed {
+                // Accelerating, up to top speed
+                let accelerated = speed + direction * c.acceleration * frames;
+                direction * accelerated.abs().min(c.top_speed)
+            } else {
+                speed
+            };
+
+        self.velocity = Vector2::new(self.ground_speed * theta.cos(),
+                                     -self.ground_speed * theta.sin());
+    }
+
+    fn update_air(&mut self, direction: f64, jump_held: bool, frames: f64) {
+        let c = &self.constants;
+
+        // Variable jump height
+        if self.jumping && !jump_held && self.velocity.y < -c.jump_release {
+            self.velocity.y = -c.jump_release;
+        }
+
+        // Air acceleration
+        if direction != 0.0 {
+            let accelerated = self.velocity.x + direction * c.air_acceleration * frames;
+            if accelerated.abs() < c.top_speed || accelerated.abs() < self.velocity.x.abs() {
+                self.velocity.x = accelerated;
+            } else if self.velocity.x.abs() < c.top_speed {
+                self.velocity.x = direction * c.top_speed;
+            }
+        }
+
+        // Air drag
+        if self.velocity.y < 0.0 && self.velocity.y > -c.jump_release {
+            self.velocity.x -= self.velocity.x * c.air_drag * frames;
+        }
+
+        // Gravity
+        self.velocity.y = (self.velocity.y + c.gravity * frames).min(c.max_fall_speed);
+    }
+
+    /// Keeps the player out of walls, relative to the current mode.
+    fn push_walls(&mut self, position: &mut Vector2, terrain: &Terrain) {
+        let sensors = self.constants.sensors;
+        let mode = if self.grounded { self.mode } else { GroundMode::Floor };
+        let forward = mode.rotate(Vector2::new(1.0, 0.0));
+        let moving = self.velocity.dot(forward);
+
+        let hit =
+            if moving < 0.0 {
+                sensors.left_wall(terrain, *position, mode).map(|hit| (hit, -1.0))
+            } else if moving > 0.0 {
+                sensors.right_wall(terrain, *position, mode).map(|hit| (hit, 1.0))
+            } else {
+                None
+            };
+
+        if let Some((hit, side)) = hit {
+            if hit.distance < 0.0 {
+                *position = *position + forward * (hit.distance * side);
+                self.velocity = self.velocity - forward * moving;
+                self.ground_speed = 0.0;
+            }
+        }
+    }
+
+    /// Snaps the player to the ground below it, following slopes
+    /// and changing modes as needed. Falls off when there is no
+    /// ground, or when going too slow on walls and ceilings.
+    fn stick_to_ground(&mut self, position: &mut Vector2, terrain: &Terrain) {
+        let sensors = self.constants.sensors;
+        let snap = (self.ground_speed.abs() + 4.0).min(MAX_SNAP_DISTANCE);
+
+        match sensors.floor(terrain, *position, self.mode) {
+            Some(hit) if hit.distance <= snap => {
+                *position = *position + self.mode.rotate(Vector2::new(0.0, hit.distance));
+                self.angle = hit.angle;
+                self.mode = GroundMode::from_angle(hit.angle);
+            },
+            _ => {
+                self.detach();
+                return;
+            },
+        }
+
+        if self.mode != GroundMode::Floor && self.ground_speed.abs() < self.constants.fall_off_speed {
+            self.detach();
+        }
+    }
+
+    /// Handles landing on floors and bumping on ceilings while on air.
+    fn collide_air(&mut self, position: &mut Vector2, terrain: &Terrain) {
+        let sensors = self.constants.sensors;
+
+        if self.velocity.y < 0.0 {
+            if let Some(hit) = sensors.ceiling(terrain, *position, GroundMode::Floor) {
+                if hit.distance < 0.0 {
+                    position.y -= hit.distance;
+                    self.velocity.y = 0.0;
+                }
+            }
+            return;
+        }
+
+        if let Some(hit) = sensors.floor(terrain, *position, GroundMode::Floor) {
+            if hit.distance < 0.0 && hit.distance >= -(self.velocity.y + 8.0) {
+                position.y += hit.distance;
+
+                // Keep whatever speed goes along the surface
+                let theta = angle_to_radians(hit.angle);
+                self.ground_speed = self.velocity.x * theta.cos() - self.velocity.y * theta.sin();
+                self.angle = hit.angle;
+                self.mode = GroundMode::from_angle(hit.angle);
+                self.grounded = true;
+                self.jumping = false;
+            }
+        }
+    }
+
+    /// Leaves the ground, keeping the current velocity.
+    fn detach(&mut self) {
+        self.grounded = false;
+        self.angle = 0;
+        self.mode = GroundMode::Floor;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collision::terrain::{ Tile, TilePlacement, Solidity };
+
+    const DT: f64 = 1.0 / 60.0;
+
+    /// Where the player stands on the flat terrain.
+    const GROUND_Y: f64 = 96.0 - 19.0;
+
+    /// Flat floor, with its surface at y = 96.
+    fn flat_terrain() -> Terrain {
+        let mut terrain = Terrain::new(200, 10);
+        let full = terrain.add_tile(Tile::full(Solidity::FULL));
+        for x in 0..200 {
+            for y in 6..10 {
+                terrain.set_tile(x, y, Some(TilePlacement::new(full)));
+            }
+        }
+        terrain
+    }
+
+    fn pad(buttons: GamepadButton) -> GamepadState {
+        let mut gamepad = GamepadState::new();
+        gamepad.set_button(buttons, true);
+        gamepad
+    }
+
+    /// Steps the controller, advancing the gamepad as the world would.
+    fn run(controller: &mut PlayerController, position: &mut Vector2,
+           gamepad: &mut GamepadState, terrain: &Terrain, frames: u32) {
+        for _ in 0..frames {
+            controller.update(position, gamepad, terrain, DT);
+            gamepad.update(DT);
+        }
+    }
+
+    /// Yields a controller standing still on the flat terrain.
+    fn landed(terrain: &Terrain) -> (PlayerController, Vector2) {
+        let mut controller = PlayerController::new(PlayerConstants::default());
+        let mut position = Vector2::new(320.0, GROUND_Y - 10.0);
+        run(&mut controller, &mut position, &mut GamepadState::new(), terrain, 30);
+        assert!(controller.is_grounded());
+        (controller, position)
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn gravity_pulls_down_to_max_fall_speed() {
+        let terrain = Terrain::new(1, 1);
+        let constants = PlayerConstants::default();
+        let mut controller = PlayerController::new(constants.clone());
+        let mut position = Vector2::zero();
+
+        run(&mut controller, &mut position, &mut GamepadState::new(), &terrain, 1);
+        assert!(close(controller.velocity().y, constants.gravity));
+        run(&mut controller, &mut position, &mut GamepadState::new(), &terrain, 2);
+        assert!(close(controller.velocity().y, constants.gravity * 3.0));
+
+        run(&mut controller, &mut position, &mut GamepadState::new(), &terrain, 200);
+        assert!(close(controller.velocity().y, constants.max_fall_speed));
+        assert!(!controller.is_grounded());
+    }
+
+    #[test]
+    fn falling_lands_on_the_floor() {
+        let terrain = flat_terrain();
+        let (controller, position) = landed(&terrain);
+        assert!(close(position.y, GROUND_Y));
+        assert_eq!(controller.angle(), 0);
+        assert_eq!(controller.mode(), GroundMode::Floor);
+        assert!(close(controller.ground_speed(), 0.0));
+    }
+
+    #[test]
+    fn running_accelerates_up_to_top_speed() {
+        let terrain = flat_terrain();
+        let constants = PlayerConstants::default();
+        let (mut controller, mut position) = landed(&terrain);
+        let mut gamepad = pad(GamepadButton::D_RGT);
+
+        run(&mut controller, &mut position, &mut gamepad, &terrain, 1);
+        assert!(close(controller.ground_speed(), constants.acceleration));
+        run(&mut controller, &mut position, &mut gamepad, &terrain, 1);
+        assert!(close(controller.ground_speed(), constants.acceleration * 2.0));
+
+        run(&mut controller, &mut position, &mut gamepad, &terrain, 300);
+        assert!(close(controller.ground_speed(), constants.top_speed));
+        assert!(close(position.y, GROUND_Y));
+    }
+
+    #[test]
+    fn friction_and_deceleration_slow_down() {
+        let terrain = flat_terrain();
+        let constants = PlayerConstants::default();
+        let (mut controller, mut position) = landed(&terrain);
+        run(&mut controller, &mut position, &mut pad(GamepadButton::D_RGT), &terrain, 300);
+
+        // Holding against the movement brakes hard
+        run(&mut controller, &mut position, &mut pad(GamepadButton::D_LFT), &terrain, 1);
+        let braked = constants.top_speed - constants.deceleration;
+        assert!(close(controller.ground_speed(), braked));
+
+        // Letting go slows down gently, until stopping
+        run(&mut controller, &mut position, &mut GamepadState::new(), &terrain, 1);
+        assert!(close(controller.ground_speed(), braked - constants.friction));
+        run(&mut controller, &mut position, &mut GamepadState::new(), &terrain, 200);
+        assert_eq!(controller.ground_speed(), 0.0);
+    }
+
+    #[test]
+    fn jumping_leaves_the_ground_and_comes_back() {
+        let terrain = flat_terrain();
+        let constants = PlayerConstants::default();
+        let (mut controller, mut position) = landed(&terrain);
+
+        run(&mut controller, &mut position, &mut pad(GamepadButton::A), &terrain, 1);
+        assert!(!controller.is_grounded());
+        assert!(close(controller.velocity().y, -constants.jump_force + constants.gravity));
+        assert!(position.y < GROUND_Y);
+
+        // Letting go early cuts the jump short
+        run(&mut controller, &mut position, &mut GamepadState::new(), &terrain, 1);
+        assert!(close(controller.velocity().y, -constants.jump_release + constants.gravity));
+
+        run(&mut controller, &mut position, &mut GamepadState::new(), &terrain, 60);
+        assert!(controller.is_grounded());
+        assert!(close(position.y, GROUND_Y));
+    }
+}
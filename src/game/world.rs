@@ -1,41 +1,93 @@
-use collision::partitioning::Quadtree;
-use game::objects::Entity;
+use collision::broadphase::{ Broadphase, BroadphaseKind };
+use game::objects::{ Entity, Player };
 use render::Renderer2D;
 use types::Vector2;
-use stdweb::unstable::TryInto;
+use common::rng::Rng;
+use collision::primitives::RaycastHit;
+use collision::terrain::{ Terrain, Tile, TilePlacement, Solidity, TILE_SIZE };
 
-use common::objects::GameObject;
+use common::objects::{ GameObject, GameObjectRef };
 use common::objects::ObjectRef;
 use common::objects::wrap_to_ref;
+use common::components::{ ComposedObject, Transform, Sprite, Collider };
+use common::systems;
+use collision::layers::CollisionLayers;
 
 use input::InputState;
+use input::devices::GamepadPoller;
+use input::replay::{ FrameInput, Replay, ReplayPlayer };
+
+use std::collections::HashMap;
+use std::rc::Rc;
 
 /// Represents a game world.
 #[derive(Clone)]
 pub struct World {
-    partitions: ObjectRef<Quadtree<Entity>>,
+    partitions: ObjectRef<Box<Broadphase<GameObject>>>,
     renderer:   Renderer2D,
     running:    bool,
     camera:     Camera,
-    pub input:  InputState,
+    rng:        ObjectRef<Rng>,
+    terrain:    Rc<Terrain>,
+
+    // For render interpolation only!
+    previous_positions: HashMap<u32, Vector2>,
+    previous_camera:    Vector2,
+
+    pub input:  ObjectRef<InputState>,
+    gamepad:    Option<ObjectRef<GamepadPoller>>,
+    recording:  Option<ObjectRef<Replay>>,
+    playback:   Option<ObjectRef<ReplayPlayer>>,
+    stepped:    bool,
 }
 
 impl World {
     /// Yields a new instance of a World.
-    pub fn new(renderer: Renderer2D, world_max_size: f64) -> Self {
+    /// # Arguments
+    /// * `renderer` - Renderer used to draw the world.
+    ///
+    /// * `world_max_size` - Size of the space to be partitioned, until
+    /// a level is loaded.
+    ///
+    /// * `seed` - Seed for the world's random number generator. The same
+    /// seed always yields the same level.
+    pub fn new(renderer: Renderer2D, world_max_size: f64, seed: u64) -> Self {
         World {
             // TODO: Figure out a better way to define depth
-            partitions: wrap_to_ref(Quadtree::new(Vector2::zero(), world_max_size / 2.0, 4)),
+            partitions: wrap_to_ref(BroadphaseKind::Quadtree {
+                center:    Vector2::zero(),
+                halfws:    [world_max_size / 2.0, world_max_size / 2.0],
+                max_depth: 4,
+            }.build()),
             renderer:   renderer,
             running:    true,
             camera:     Camera::new(Vector2::new(640.0, 360.0)),
-            input:      InputState::new(),
+            rng:        wrap_to_ref(Rng::new(seed)),
+            terrain:    Rc::new(Terrain::new(0, 0)),
+            previous_positions: HashMap::new(),
+            previous_camera:    Vector2::zero(),
+            input:      wrap_to_ref(InputState::new()),
+            gamepad:    None,
+            recording:  None,
+            playback:   None,
+            stepped:    false,
         }
     }
 
     /// Initializes the world.
     /// Currently, this serves only debugging purposes.
     pub fn init(&mut self) {
+        self.terrain = Rc::new(test_terrain());
+
+        // Partition the level itself, rather than a square around
+        // the origin
+        let size = self.terrain.size();
+        self.set_broadphase(BroadphaseKind::Quadtree {
+            center:    size * 0.5,
+            halfws:    [size.x / 2.0, size.y / 2.0],
+            max_depth: 4,
+        });
+
         // Add test entities
         let mut entity_pos = vec![];
         let colors = vec!["#ff00007f", "#00ff007f", "#0000ff7f"];
@@ -50,9 +102,10 @@ impl World {
         let iterations = 16;
         for _ in 0..iterations
         {
+            let mut rng = self.rng.borrow_mut();
             let pos = Vector2 {
-                x: js!(return 100.0 + Math.floor((Math.random() * 400));).try_into().unwrap(),
-                y: js!(return 100.0 + Math.floor((Math.random() * 400));).try_into().unwrap()
+                x: 100.0 + rng.range(0.0, 400.0).floor(),
+                y: 100.0 + rng.range(0.0, 400.0).floor(),
             };
             entity_pos.push(pos);
         }
@@ -62,15 +115,28 @@ impl World {
             let mut color_idx = 1;
             for pos in entity_pos {
                 println!("Adding entity at ({}, {})...", pos.x, pos.y);
-                self.partitions.borrow_mut().add(wrap_to_ref(Entity::new(color_idx as u32, pos, colors[color_idx % 3])));
+                let entity: GameObjectRef =
+                    wrap_to_ref(Entity::new(color_idx as u32, pos, colors[color_idx % 3],
+                                            &mut *self.rng.borrow_mut()));
+                self.partitions.borrow_mut().add(entity);
                 color_idx += 1;
             }
         }
 
+        // Add rings over the jump-through platform
+        for ring in 0..5 {
+            let position = Vector2::new(((13 + ring) * TILE_SIZE) as f64, (13 * TILE_SIZE) as f64);
+            let object: GameObjectRef = wrap_to_ref(
+                ComposedObject::new(100 + ring as u32)
+                    .with(Transform::at(position))
+                    .with(Sprite::circle("#ffd700", 6.0))
+                    .with(Collider::new(6.0).layers(CollisionLayers::RING, CollisionLayers::PLAYER)));
+            self.partitions.borrow_mut().add(object);
+        }
+
         // Add the player
         {
-            let player = wrap_to_ref(
-                Entity::new(0, Vector2::new(320.0, 180.0), "#0000007f"));
+            let player: GameObjectRef = wrap_to_ref(Player::new(0, Vector2::new(320.0, 180.0)));
             self.partitions.borrow_mut().add(player.clone());
             // Make camera follow it
             self.camera.follow(Some(player));
@@ -78,48 +144,317 @@ impl World {
 
         // Set camera position
         self.camera.translate(Vector2::new(320.0, 180.0));
+        self.previous_camera = self.camera.viewport.center;
     }
 
     /// Executes a step in the World, updating logic, rendering and collision.
+    /// This is the same as an update followed by a non-interpolated render.
     pub fn game_step(&mut self, dt: f64) {
+        self.update(dt);
+        self.render(1.0);
+    }
+
+    /// Updates logic and collision on the World, without rendering.
+    /// Meant to be called with a fixed `dt`; see `FixedTimestep`.
+    pub fn update(&mut self, dt: f64) {
+        self.stepped = true;
+
+        // == Gamepad polling pass == //
+        if let Some(ref gamepad) = self.gamepad {
+            gamepad.borrow_mut().poll(&mut self.input.borrow_mut().gamepad);
+        }
+
+        // == Replay pass == //
+        let frame = match self.playback {
+            Some(ref player) => player.borrow_mut().next_frame(),
+            None => None,
+        };
+        match frame {
+            Some(frame) => frame.apply(&mut self.input.borrow_mut()),
+            None => self.playback = None,
+        }
+
+        if let Some(ref recording) = self.recording {
+            recording.borrow_mut().record(FrameInput::capture(&self.input.borrow()), dt);
+        }
+
         // == Collision pass == //
         self.partitions.borrow_mut().test_collisions();
 
-        // == Clear screen pass == //
-        self.renderer.clear();
-        self.renderer.draw_box("#aaa", Vector2::zero(), self.camera.viewport_size());
-
         // == Camera update pass == //
+        self.previous_camera = self.camera.viewport.center;
         self.camera.update();
         let local_camera_boundary = self.camera.bounding_circle();
-        self.renderer.update_camera_position({
-            local_camera_boundary.center - self.camera.half_viewport_size()
-        });
 
-        // == Object iteration pass == //
-        let iterator = self.partitions.borrow().local_iter(local_camera_boundary);
-        for object in iterator {
-            //println!("Render one");
-            // == Update pass == //
+        // == Object update pass == //
+        self.previous_positions.clear();
+        let objects = self.partitions.borrow().local_objects(local_camera_boundary);
+        for object in objects {
             let old_position = object.borrow().get_position();
-            object.borrow_mut().update(dt);
-            let new_position = object.borrow().get_position();
 
-            // == Draw pass == //
-            object.borrow_mut().draw(&self.renderer);
+            // Component systems run around the object's own update
+            if let Some(components) = object.borrow_mut().components() {
+                systems::begin_update(components);
+            }
+            object.borrow_mut().update(dt, self);
+            if let Some(components) = object.borrow_mut().components() {
+                systems::update(components, dt);
+            }
+
+            let new_position = object.borrow().get_position();
+            self.previous_positions.insert(object.borrow().get_id(), old_position);
 
             // Relocation scheduling
             if old_position != new_position {
-                self.partitions.borrow_mut().schedule_update(object.clone(), old_position);
+                self.partitions.borrow_mut().schedule_update(object.clone());
             }
-
-
         }
 
         // == Relocation pass == //
-        let _ = self.partitions.borrow_mut().update_positions();
+        if let Err(failures) = self.partitions.borrow_mut().update_positions() {
+            for (id, reason) in failures {
+                println!("Could not relocate object #{}: {}", id, reason);
+            }
+        }
         self.camera.update();
+
+        // == Input pass == //
+        self.input.borrow_mut().update(dt);
     }
+
+    /// Renders the World.
+    /// # Arguments
+    /// * `alpha` - How far, from `0.0` to `1.0`, we are between the
+    /// previous update and the last one. Objects and camera are drawn
+    /// interpolated between both states.
+    pub fn render(&mut self, alpha: f64) {
+        // == Clear screen pass == //
+        self.renderer.clear();
+        self.renderer.draw_box("#aaa", Vector2::zero(), self.camera.viewport_size());
+
+        // == Camera interpolation pass == //
+        let camera_position = lerp(self.previous_camera, self.camera.viewport.center, alpha);
+        self.renderer.update_camera_position(camera_position - self.camera.half_viewport_size());
+
+        // == Terrain draw pass == //
+        self.draw_terrain();
+
+        // == Draw pass == //
+        let local_camera_boundary = self.camera.bounding_circle();
+        let objects = self.partitions.borrow().local_objects(local_camera_boundary);
+        for object in objects {
+            let position = object.borrow().get_position();
+            let previous = match self.previous_positions.get(&object.borrow().get_id()) {
+                Some(previous) => *previous,
+                None => position,
+            };
+            self.renderer.set_draw_offset(lerp(previous, position, alpha) - position);
+            object.borrow_mut().draw(&self.renderer);
+            if let Some(components) = object.borrow_mut().components() {
+                systems::draw(components, &self.renderer);
+            }
+        }
+        self.renderer.set_draw_offset(Vector2::zero());
+    }
+
+    /// Draws the solid parts of the terrain that are on screen.
+    /// Neighbouring columns of the same height are drawn together.
+    fn draw_terrain(&self) {
+        let tile_size = TILE_SIZE as f64;
+        let min = self.camera.viewport.center - self.camera.half_viewport_size();
+        let max = self.camera.viewport.center + self.camera.half_viewport_size();
+        let first = ((min.x / tile_size).floor() as i64, (min.y / tile_size).floor() as i64);
+        let last  = ((max.x / tile_size).ceil() as i64, (max.y / tile_size).ceil() as i64);
+
+        for y in first.1..last.1 {
+            for x in first.0..last.0 {
+                let (tile, placement) = match self.terrain.tile_at(x, y) {
+                    Some(found) => found,
+                    None => continue,
+                };
+                let corner = Vector2::new(x as f64 * tile_size, y as f64 * tile_size);
+                let height_at = |column: usize| {
+                    let index = if placement.flip_x { TILE_SIZE - 1 - column } else { column };
+                    tile.heights[index] as f64
+                };
+
+                let mut start = 0;
+                while start < TILE_SIZE {
+                    let height = height_at(start);
+                    let mut end = start + 1;
+                    while end < TILE_SIZE && height_at(end) == height {
+                        end += 1;
+                    }
+                    if height > 0.0 {
+                        let top = if placement.flip_y { 0.0 } else { tile_size - height };
+                        self.renderer.draw_box_rel("#4a4",
+                                                   corner + Vector2::new(start as f64, top),
+                                                   Vector2::new((end - start) as f64, height));
+                    }
+                    start = end;
+                }
+            }
+        }
+    }
+
+    /// Casts a ray against the objects of the world, yielding the
+    /// nearest one hit, if any.
+    /// # Arguments
+    /// * `origin` - Point where the ray starts.
+    ///
+    /// * `dir` - Direction of the ray.
+    ///
+    /// * `max_dist` - How far the ray goes.
+    pub fn raycast(&self, origin: Vector2, dir: Vector2, max_dist: f64) -> Option<(GameObjectRef, RaycastHit)> {
+        self.partitions.borrow().raycast(origin, dir, max_dist)
+    }
+
+    /// Replaces the broadphase of the world, moving every object
+    /// over to the new one. Levels should pick whichever suits their
+    /// shape; e.g. sweep and prune for long horizontal ones.
+    /// # Arguments
+    /// * `kind` - Broadphase to be built.
+    pub fn set_broadphase(&mut self, kind: BroadphaseKind) {
+        let objects = self.partitions.borrow().objects();
+        let mut partitions = kind.build();
+        for object in objects {
+            partitions.add(object);
+        }
+        self.partitions = wrap_to_ref(partitions);
+    }
+
+    /// Yields the terrain of the world.
+    pub fn terrain(&self) -> Rc<Terrain> {
+        self.terrain.clone()
+    }
+
+    /// Replaces the terrain of the world.
+    /// # Arguments
+    /// * `terrain` - New terrain for the world.
+    pub fn set_terrain(&mut self, terrain: Terrain) {
+        self.terrain = Rc::new(terrain);
+    }
+
+    /// Connects a physical gamepad to the world. It will be polled
+    /// once per update, before anything else.
+    /// # Arguments
+    /// * `poller` - Poller for the gamepad device.
+    pub fn connect_gamepad(&mut self, poller: GamepadPoller) {
+        self.gamepad = Some(wrap_to_ref(poller));
+    }
+
+    /// Starts recording the input of every update, along with the
+    /// world's seed. Any recording in progress is discarded.
+    /// Replays only hold the seed, not where objects are, so they
+    /// are played back from the level as first built. Recording must
+    /// therefore start before the world is first updated; yields an
+    /// error otherwise.
+    pub fn start_recording(&mut self) -> Result<(), &'static str> {
+        if self.stepped {
+            return Err("Recording must start before the first update");
+        }
+        self.recording = Some(wrap_to_ref(Replay::new(self.seed())));
+        Ok(())
+    }
+
+    /// Stops recording, yielding what was recorded so far.
+    pub fn stop_recording(&mut self) -> Option<Replay> {
+        self.recording.take().map(|recording| recording.borrow().clone())
+    }
+
+    /// Starts playing a replay back, overriding input on every
+    /// update until the replay is over. The world should have been
+    /// built and initialized from the replay's seed.
+    /// # Arguments
+    /// * `replay` - Replay to be played back.
+    pub fn start_playback(&mut self, replay: Replay) {
+        self.playback = Some(wrap_to_ref(ReplayPlayer::new(replay)));
+    }
+
+    /// Whether a replay is being played back and still has frames left.
+    pub fn is_playing_back(&self) -> bool {
+        match self.playback {
+            Some(ref player) => !player.borrow().finished(),
+            None => false,
+        }
+    }
+
+    /// Yields the seed of the world's random number generator.
+    pub fn seed(&self) -> u64 {
+        self.rng.borrow().seed()
+    }
+
+    /// Restarts the world's random number generator with a new seed.
+    /// Call this before `init` so that the level is built from it.
+    /// # Arguments
+    /// * `seed` - New seed for the generator.
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng.borrow_mut().reseed(seed);
+    }
+
+    /// Yields the ids and positions of every object in the world.
+    pub fn object_positions(&self) -> Vec<(u32, Vector2)> {
+        self.partitions.borrow().objects().iter()
+            .map(|object| {
+                let object = object.borrow();
+                (object.get_id(), object.get_position())
+            })
+            .collect()
+    }
+}
+
+/// Builds a small level for testing: a floor with a slope up to a
+/// plateau, a jump-through platform and a wall at each end.
+fn test_terrain() -> Terrain {
+    let mut terrain = Terrain::new(50, 25);
+    let full = terrain.add_tile(Tile::full(Solidity::FULL));
+    let platform = terrain.add_tile(Tile::full(Solidity::TOP));
+
+    // 45 degree slope going up to the right
+    let mut heights = [0; TILE_SIZE];
+    for (column, height) in heights.iter_mut().enumerate() {
+        *height = column as u8 + 1;
+    }
+    let slope = terrain.add_tile(Tile::from_heights(heights, 0xe0, Solidity::FULL));
+
+    // Floor, from row 19 down
+    for x in 0..50 {
+        for y in 19..25 {
+            terrain.set_tile(x, y, Some(TilePlacement::new(full)));
+        }
+    }
+
+    // Slope and plateau
+    for step in 0..3 {
+        terrain.set_tile(30 + step, 18 - step, Some(TilePlacement::new(slope)));
+        for y in (19 - step)..19 {
+            terrain.set_tile(30 + step, y, Some(TilePlacement::new(full)));
+        }
+    }
+    for x in 33..45 {
+        for y in 16..19 {
+            terrain.set_tile(x, y, Some(TilePlacement::new(full)));
+        }
+    }
+
+    // Jump-through platform
+    for x in 12..18 {
+        terrain.set_tile(x, 14, Some(TilePlacement::new(platform)));
+    }
+
+    // Walls
+    for y in 0..19 {
+        terrain.set_tile(0, y, Some(TilePlacement::new(full)));
+        terrain.set_tile(49, y, Some(TilePlacement::new(full)));
+    }
+
+    terrain
+}
+
+/// Linear interpolation between two points.
+fn lerp(from: Vector2, to: Vector2, alpha: f64) -> Vector2 {
+    Vector2::new(from.x + (to.x - from.x) * alpha,
+                 from.y + (to.y - from.y) * alpha)
 }
 
 
@@ -249,3 +584,124 @@ impl Delimitable for Camera {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use render::software::SoftwareBackend;
+    use common::components::{ Components, PhysicsBody };
+
+    fn world(seed: u64) -> World {
+        let renderer = Renderer2D::new(Rc::new(SoftwareBackend::new(64, 64)));
+        let mut world = World::new(renderer, 800.0, seed);
+        world.init();
+        world
+    }
+
+    #[test]
+    fn same_seed_builds_same_level() {
+        let first = world(2018);
+        let second = world(2018);
+        assert_eq!(first.object_positions(), second.object_positions());
+
+        // Entities are placed at random
+        let other = world(2019);
+        assert!(first.object_positions() != other.object_positions());
+    }
+
+    /// Moves its transform on its own update, rather than through a
+    /// physics body.
+    struct Walker {
+        components: Components,
+    }
+
+    impl GameObject for Walker {
+        fn update(&mut self, _dt: f64, _world: &World) {
+            if let Some(ref mut transform) = self.components.transform {
+                transform.translate(Vector2::new(200.0, 0.0));
+            }
+        }
+
+        fn components(&mut self) -> Option<&mut Components> {
+            Some(&mut self.components)
+        }
+
+        fn bounding_circle(&self) -> Circle {
+            Circle { center: self.components.position(), radius: 5.0 }
+        }
+
+        fn get_position(&self) -> Vector2 {
+            self.components.position()
+        }
+
+        fn get_id(&self) -> u32 {
+            1
+        }
+
+        fn is_fast(&self) -> bool {
+            true
+        }
+
+        fn displacement(&self) -> Vector2 {
+            self.components.transform.as_ref().unwrap().displacement
+        }
+    }
+
+    /// An empty world, with a slow target at the origin.
+    fn target_world() -> (World, ObjectRef<ComposedObject>) {
+        let renderer = Renderer2D::new(Rc::new(SoftwareBackend::new(64, 64)));
+        let world = World::new(renderer, 800.0, 1);
+        let target = wrap_to_ref(ComposedObject::new(2)
+            .with(Transform::at(Vector2::zero()))
+            .with(Collider::new(5.0)));
+        world.partitions.borrow_mut().add(target.clone());
+        (world, target)
+    }
+
+    fn touching(object: &ObjectRef<ComposedObject>) -> u32 {
+        object.borrow_mut().components().unwrap().collider.as_ref().unwrap().touching
+    }
+
+    #[test]
+    fn fast_composed_objects_hit_what_they_pass_through() {
+        let (mut world, target) = target_world();
+        let bullet = wrap_to_ref(ComposedObject::new(1)
+            .with(Transform::at(Vector2::new(-100.0, 0.0)))
+            .with(PhysicsBody::new(Vector2::new(12000.0, 0.0)))
+            .with(Collider { fast: true, ..Collider::new(5.0) }));
+        world.partitions.borrow_mut().add(bullet.clone());
+
+        // Collisions are tested before moving, so the second update
+        // sweeps the first step, which goes right through the target
+        world.update(1.0 / 60.0);
+        assert_eq!(bullet.borrow().get_position(), Vector2::new(100.0, 0.0));
+        assert_eq!(bullet.borrow().displacement(), Vector2::new(200.0, 0.0));
+        world.update(1.0 / 60.0);
+        assert_eq!((touching(&bullet), touching(&target)), (1, 1));
+    }
+
+    #[test]
+    fn objects_keep_what_they_moved_on_their_own() {
+        let (mut world, target) = target_world();
+        let mut components = Components::new();
+        components.attach(Transform::at(Vector2::new(-100.0, 0.0)));
+        let walker = wrap_to_ref(Walker { components: components });
+        world.partitions.borrow_mut().add(walker.clone());
+
+        world.update(1.0 / 60.0);
+        assert_eq!(walker.borrow().displacement(), Vector2::new(200.0, 0.0));
+        world.update(1.0 / 60.0);
+        assert_eq!(touching(&target), 1);
+    }
+
+    #[test]
+    fn recording_only_starts_before_the_first_update() {
+        let mut fresh = world(7);
+        assert_eq!(fresh.start_recording(), Ok(()));
+        fresh.update(1.0 / 60.0);
+        assert!(fresh.stop_recording().is_some());
+
+        assert_eq!(fresh.start_recording(), Err("Recording must start before the first update"));
+        assert!(fresh.stop_recording().is_none());
+    }
+}
@@ -0,0 +1,350 @@
+//! Submodule for physical input devices.
+//! Devices are polled once per frame, and their snapshots are then
+//! applied to a `GamepadState`.
+
+use types::Vector2;
+use input::{ GamepadState, GamepadButton };
+
+/// Buttons of the standard gamepad layout, in the order they are
+/// reported by the browser's Gamepad API.
+const STANDARD_BUTTONS: [GamepadButton; 16] = [
+    GamepadButton::A,
+    GamepadButton::B,
+    GamepadButton::X,
+    GamepadButton::Y,
+    GamepadButton::LB,
+    GamepadButton::RB,
+    GamepadButton::LT,
+    GamepadButton::RT,
+    GamepadButton::BACK,
+    GamepadButton::START,
+    GamepadButton::LS,
+    GamepadButton::RS,
+    GamepadButton::D_UP,
+    GamepadButton::D_DWN,
+    GamepadButton::D_LFT,
+    GamepadButton::D_RGT,
+];
+
+/// Value past which a digital button counts as pressed.
+const BUTTON_THRESHOLD: f64 = 0.5;
+
+/// Dead zones applied to analog gamepad inputs.
+#[derive(Clone, Copy, Debug)]
+pub struct DeadZones {
+    /// Radial dead zone for both sticks, from `0.0` to `1.0`.
+    pub stick:   f64,
+    /// Value past which an analog trigger counts as pressed.
+    pub trigger: f64,
+}
+
+impl Default for DeadZones {
+    fn default() -> Self {
+        DeadZones {
+            stick:   0.2,
+            trigger: 0.1,
+        }
+    }
+}
+
+
+/// Represents a snapshot of a gamepad with standard layout:
+/// button values from `0.0` to `1.0`, and axes from `-1.0` to `1.0`
+/// (left stick X and Y, then right stick X and Y).
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawGamepad {
+    pub buttons: Vec<f64>,
+    pub axes:    Vec<f64>,
+}
+
+impl RawGamepad {
+    /// Yields a snapshot with no buttons pressed and sticks at rest.
+    pub fn neutral() -> Self {
+        RawGamepad {
+            buttons: vec![0.0; STANDARD_BUTTONS.len()],
+            axes:    vec![0.0; 4],
+        }
+    }
+
+    /// Yields this snapshot with a button pressed.
+    /// # Arguments
+    /// * `button` - Button to be pressed. Buttons outside the
+    /// standard layout are ignored.
+    pub fn with_button(mut self, button: GamepadButton) -> Self {
+        for (index, standard) in STANDARD_BUTTONS.iter().enumerate() {
+            if button.contains(*standard) && index < self.buttons.len() {
+                self.buttons[index] = 1.0;
+            }
+        }
+        self
+    }
+
+    /// Yields this snapshot with the given stick values.
+    pub fn with_sticks(mut self, lstick: Vector2, rstick: Vector2) -> Self {
+        self.axes = vec![lstick.x, lstick.y, rstick.x, rstick.y];
+        self
+    }
+
+    /// Yields the buttons pressed on this snapshot.
+    pub fn buttons_pressed(&self, dead_zones: &DeadZones) -> GamepadButton {
+        let mut pressed = GamepadButton::NONE;
+        for (index, value) in self.buttons.iter().enumerate().take(STANDARD_BUTTONS.len()) {
+            let button = STANDARD_BUTTONS[index];
+            let threshold =
+                if button == GamepadButton::LT || button == GamepadButton::RT {
+                    dead_zones.trigger
+                } else {
+                    BUTTON_THRESHOLD
+                };
+            if *value > threshold {
+                pressed |= button;
+            }
+        }
+        pressed
+    }
+
+    /// Yields the left stick, with the dead zone applied.
+    pub fn left_stick(&self, dead_zones: &DeadZones) -> Vector2 {
+        self.stick(0, dead_zones.stick)
+    }
+
+    /// Yields the right stick, with the dead zone applied.
+    pub fn right_stick(&self, dead_zones: &DeadZones) -> Vector2 {
+        self.stick(2, dead_zones.stick)
+    }
+
+    fn stick(&self, first_axis: usize, dead_zone: f64) -> Vector2 {
+        let axis = |index: usize| *self.axes.get(index).unwrap_or(&0.0);
+        apply_dead_zone(Vector2::new(axis(first_axis), axis(first_axis + 1)), dead_zone)
+    }
+}
+
+/// Applies a radial dead zone to a stick, rescaling what is left so
+/// that the stick still goes smoothly from `0.0` to `1.0`.
+fn apply_dead_zone(stick: Vector2, dead_zone: f64) -> Vector2 {
+    let magnitude = stick.length();
+    if magnitude <= dead_zone || dead_zone >= 1.0 {
+        return Vector2::zero();
+    }
+    let scaled = ((magnitude - dead_zone) / (1.0 - dead_zone)).min(1.0);
+    stick * (scaled / magnitude)
+}
+
+
+/// Common trait for physical gamepads.
+pub trait GamepadDevice {
+    /// Yields the current state of the gamepad, or `None` if it
+    /// is not connected.
+    fn poll(&mut self) -> Option<RawGamepad>;
+}
+
+/// Polls a gamepad device and applies its state on a `GamepadState`.
+pub struct GamepadPoller {
+    device:         Box<GamepadDevice>,
+    pub dead_zones: DeadZones,
+}
+
+impl GamepadPoller {
+    /// Creates a new poller.
+    /// # Arguments
+    /// * `device` - Device to be polled.
+    ///
+    /// * `dead_zones` - Dead zones applied to the device's snapshots.
+    pub fn new(device: Box<GamepadDevice>, dead_zones: DeadZones) -> Self {
+        GamepadPoller {
+            device:     device,
+            dead_zones: dead_zones,
+        }
+    }
+
+    /// Polls the device once, updating the gamepad state. A
+    /// disconnected device is treated as if it was at rest.
+    pub fn poll(&mut self, state: &mut GamepadState) {
+        let raw = self.device.poll().unwrap_or_else(RawGamepad::neutral);
+        state.apply_raw(&raw, &self.dead_zones);
+    }
+}
+
+
+/// Gamepad which plays back a fixed sequence of snapshots, one per
+/// poll. After the last one, it keeps yielding it.
+pub struct ScriptedGamepad {
+    frames:  Vec<Option<RawGamepad>>,
+    current: usize,
+}
+
+impl ScriptedGamepad {
+    /// Creates a new scripted gamepad.
+    /// # Arguments
+    /// * `frames` - Snapshots to be yielded, in order. `None` stands
+    /// for a disconnected gamepad.
+    pub fn new(frames: Vec<Option<RawGamepad>>) -> Self {
+        ScriptedGamepad {
+            frames:  frames,
+            current: 0,
+        }
+    }
+}
+
+impl GamepadDevice for ScriptedGamepad {
+    fn poll(&mut self) -> Option<RawGamepad> {
+        let frame = self.frames.get(self.current)
+            .or(self.frames.last())
+            .and_then(|frame| frame.clone());
+        if self.current < self.frames.len() {
+            self.current += 1;
+        }
+        frame
+    }
+}
+
+
+/// Gamepad read from the browser's Gamepad API.
+#[cfg(target_arch = "wasm32")]
+pub struct BrowserGamepad {
+    index: u32,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl BrowserGamepad {
+    /// Creates a new browser gamepad.
+    /// # Arguments
+    /// * `index` - Index of the gamepad on `navigator.getGamepads()`.
+    pub fn new(index: u32) -> Self {
+        BrowserGamepad { index: index }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl GamepadDevice for BrowserGamepad {
+    fn poll(&mut self) -> Option<RawGamepad> {
+        use stdweb::unstable::TryInto;
+
+        // Yields the amount of buttons, then the button values,
+        // then the axes; or an empty array with no usable gamepad.
+        let data: Vec<f64> = js! {
+            var pads = navigator.getGamepads ? navigator.getGamepads() : [];
+            var pad = pads[@{self.index}];
+            if (!pad || !pad.connected || pad.mapping != "standard") {
+                return [];
+            }
+            var data = [pad.buttons.length];
+            for (var i = 0; i < pad.buttons.length; i += 1) {
+                data.push(pad.buttons[i].value);
+            }
+            for (var j = 0; j < pad.axes.length; j += 1) {
+                data.push(pad.axes[j]);
+            }
+            return data;
+        }.try_into().ok()?;
+
+        let buttons = *data.first()? as usize;
+        if data.len() < buttons + 1 {
+            return None;
+        }
+        Some(RawGamepad {
+            buttons: data[1..buttons + 1].to_vec(),
+            axes:    data[buttons + 1..].to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn poller(frames: Vec<Option<RawGamepad>>) -> GamepadPoller {
+        GamepadPoller::new(Box::new(ScriptedGamepad::new(frames)), DeadZones::default())
+    }
+
+    fn with_stick(lstick: Vector2) -> Option<RawGamepad> {
+        Some(RawGamepad::neutral().with_sticks(lstick, Vector2::zero()))
+    }
+
+    fn with_value(index: usize, value: f64) -> Option<RawGamepad> {
+        let mut raw = RawGamepad::neutral();
+        raw.buttons[index] = value;
+        Some(raw)
+    }
+
+    #[test]
+    fn sticks_are_clamped_by_dead_zone() {
+        let mut poller = poller(vec![with_stick(Vector2::new(0.15, 0.1)),
+                                     with_stick(Vector2::new(0.6, 0.0)),
+                                     with_stick(Vector2::new(1.0, 1.0))]);
+        let mut state = GamepadState::new();
+
+        poller.poll(&mut state);
+        assert_eq!(state.left_stick(), Vector2::zero());
+
+        // What is left past the dead zone is rescaled
+        poller.poll(&mut state);
+        assert!((state.left_stick().x - 0.5).abs() < 1e-9);
+
+        // Corners don't go past a full tilt
+        poller.poll(&mut state);
+        assert!((state.left_stick().length() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn buttons_need_to_pass_their_threshold() {
+        // A, then LT
+        let mut poller = poller(vec![with_value(0, 0.4), with_value(0, 0.6),
+                                     with_value(6, 0.05), with_value(6, 0.15)]);
+        let mut state = GamepadState::new();
+
+        poller.poll(&mut state);
+        assert!(!state.button_pressed(GamepadButton::A));
+        poller.poll(&mut state);
+        assert!(state.button_pressed(GamepadButton::A));
+
+        // Triggers use their own dead zone
+        poller.poll(&mut state);
+        assert!(!state.button_pressed(GamepadButton::LT));
+        poller.poll(&mut state);
+        assert!(state.button_pressed(GamepadButton::LT));
+    }
+
+    #[test]
+    fn scripts_hold_their_last_frame() {
+        let pressed = Some(RawGamepad::neutral().with_button(GamepadButton::START));
+        let mut poller = poller(vec![None, pressed]);
+        let mut state = GamepadState::new();
+
+        // Disconnected gamepads are at rest
+        poller.poll(&mut state);
+        assert!(!state.button_pressed(GamepadButton::START));
+        for _ in 0..3 {
+            poller.poll(&mut state);
+            assert!(state.button_pressed(GamepadButton::START));
+        }
+    }
+
+    #[test]
+    fn gamepad_merges_with_held_keys() {
+        let up = Some(RawGamepad::neutral().with_button(GamepadButton::D_UP | GamepadButton::A));
+        let mut poller = poller(vec![Some(RawGamepad::neutral()), up.clone(),
+                                     Some(RawGamepad::neutral()), up]);
+        let mut state = GamepadState::new();
+        state.map_default_keys();
+
+        // A key held before the gamepad is polled is kept
+        state.set_button_mapped("ArrowUp", true);
+        state.set_axis_mapped("ArrowUp", true);
+        poller.poll(&mut state);
+        assert!(state.button_pressed(GamepadButton::D_UP));
+        assert_eq!(state.left_stick(), Vector2::new(0.0, -1.0));
+
+        // Releasing a button on the gamepad keeps it held by the key
+        poller.poll(&mut state);
+        assert!(state.button_pressed(GamepadButton::D_UP | GamepadButton::A));
+        poller.poll(&mut state);
+        assert!(state.button_pressed(GamepadButton::D_UP));
+        assert!(!state.button_pressed(GamepadButton::A));
+
+        // And releasing the key keeps it held by the gamepad
+        poller.poll(&mut state);
+        state.set_button_mapped("ArrowUp", false);
+        assert!(state.button_pressed(GamepadButton::D_UP));
+    }
+}
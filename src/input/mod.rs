@@ -1,5 +1,10 @@
 use types::Vector2;
-use std::collections::HashMap;
+use std::collections::{ HashMap, HashSet };
+
+pub mod devices;
+pub mod replay;
+
+use self::devices::{ RawGamepad, DeadZones };
 
 // ===== Mouse =====
 
@@ -16,6 +21,8 @@ bitflags! {
 pub struct MouseState {
     pub position: Vector2,
     pub buttons:  u8,
+
+    prev_buttons: u8,
 }
 
 impl MouseState {
@@ -23,8 +30,39 @@ impl MouseState {
         MouseState {
             position: Vector2::zero(),
             buttons:  0,
+            prev_buttons: 0,
         }
     }
+
+    // Setters
+    pub fn set_button(&mut self, button: MouseButton, state: bool) {
+        if state {
+            self.buttons = self.buttons | button.bits;
+        } else {
+            self.buttons = self.buttons & !button.bits;
+        }
+    }
+
+    /// Takes a snapshot of the buttons, so that presses and
+    /// releases can be detected on the next frame.
+    pub fn update(&mut self) {
+        self.prev_buttons = self.buttons;
+    }
+
+    // Getters
+    pub fn button_pressed(&self, button: MouseButton) -> bool {
+        self.buttons & button.bits != 0
+    }
+
+    /// Whether a button went down since the last frame.
+    pub fn just_pressed(&self, button: MouseButton) -> bool {
+        self.buttons & !self.prev_buttons & button.bits != 0
+    }
+
+    /// Whether a button went up since the last frame.
+    pub fn just_released(&self, button: MouseButton) -> bool {
+        !self.buttons & self.prev_buttons & button.bits != 0
+    }
 }
 
 // ===== Gamepad =====
@@ -71,7 +109,16 @@ pub struct GamepadState {
     pub rstick:   Vector2,
     pub buttons:  u32,
 
-    mappings: HashMap<&'static str, GamepadButton>,
+    prev_buttons:  u32,
+    hold_times:    [f64; 16],
+    mappings:      HashMap<&'static str, GamepadButton>,
+    axis_mappings: HashMap<&'static str, GamepadAxisSim>,
+    held_keys:     HashSet<String>,
+    stick_sim:     GamepadAxisSim,
+    stick_ramp:    f64,
+    sim_sticks:    [Vector2; 2],
+    pad_sticks:    [Vector2; 2],
+    pad_buttons:   u32,
 }
 
 impl GamepadState {
@@ -80,7 +127,16 @@ impl GamepadState {
             lstick:   Vector2::zero(),
             rstick:   Vector2::zero(),
             buttons:  0,
-            mappings: HashMap::new(),
+            prev_buttons:  0,
+            hold_times:    [0.0; 16],
+            mappings:      HashMap::new(),
+            axis_mappings: HashMap::new(),
+            held_keys:     HashSet::new(),
+            stick_sim:     GamepadAxisSim::NONE,
+            stick_ramp:    0.0,
+            sim_sticks:    [Vector2::zero(); 2],
+            pad_sticks:    [Vector2::zero(); 2],
+            pad_buttons:   0,
         }
     }
 
@@ -93,17 +149,154 @@ impl GamepadState {
         }
     }
 
-    pub fn set_button_mapped(&mut self, key: &str, state: bool) {
-        let button = 
+    /// Sets the state of the button mapped to a keyboard key.
+    /// The button is only released once no other key mapped to it
+    /// is held. Yields whether the key was mapped to any button at
+    /// all.
+    pub fn set_button_mapped(&mut self, key: &str, state: bool) -> bool {
+        let button =
             match self.mappings.get(key) {
                 Some(button) => *button,
-                None => GamepadButton::NONE,
+                None => return false,
             };
-        self.set_button(button, state);
+
+        self.hold_key(key, state);
+        if state {
+            self.set_button(button, true);
+        } else {
+            // The physical gamepad may be holding it as well
+            let still_held = self.key_buttons().bits | self.pad_buttons;
+            self.buttons &= !(button.bits & !still_held);
+        }
+        true
     }
 
+    /// Sets which directions of the simulated sticks are being held.
+    /// Without a ramp time, the sticks snap to their new values right
+    /// away; otherwise they move towards them on `update`.
     pub fn set_stick_sim(&mut self, state: GamepadAxisSim) {
-        unimplemented!();
+        self.stick_sim = state;
+        if self.stick_ramp <= 0.0 {
+            let (lstick, rstick) = self.stick_sim_targets();
+            self.sim_sticks = [lstick, rstick];
+            self.combine_sticks();
+        }
+    }
+
+    /// Applies a snapshot of a physical gamepad. Buttons are only
+    /// changed when the gamepad changes them, and are not released
+    /// while a key mapped to them is held, so that keyboard bindings
+    /// keep working alongside it. Sticks outside their dead zones
+    /// take precedence over simulated ones.
+    /// # Arguments
+    /// * `raw` - Snapshot of a standard-layout gamepad.
+    ///
+    /// * `dead_zones` - Dead zones to be applied to the snapshot.
+    pub fn apply_raw(&mut self, raw: &RawGamepad, dead_zones: &DeadZones) {
+        let pad_buttons = raw.buttons_pressed(dead_zones).bits;
+        let changed = pad_buttons ^ self.pad_buttons;
+        let held = pad_buttons | self.key_buttons().bits;
+        self.buttons = (self.buttons & !changed) | (held & changed);
+        self.pad_buttons = pad_buttons;
+
+        self.pad_sticks = [raw.left_stick(dead_zones), raw.right_stick(dead_zones)];
+        self.combine_sticks();
+    }
+
+    /// Sets the state of the simulated stick direction mapped to a
+    /// keyboard key. Yields whether the key was mapped to any
+    /// direction at all.
+    pub fn set_axis_mapped(&mut self, key: &str, state: bool) -> bool {
+        let axis =
+            match self.axis_mappings.get(key) {
+                Some(axis) => *axis,
+                None => return false,
+            };
+
+        self.hold_key(key, state);
+        let mut stick_sim = self.stick_sim;
+        if state {
+            stick_sim.insert(axis);
+        } else {
+            // Directions stay held while other keys mapped to them are
+            let still_held = self.held_keys.iter()
+                .filter_map(|key| self.axis_mappings.get(key.as_str()))
+                .fold(GamepadAxisSim::NONE, |held, axis| held | *axis);
+            stick_sim.remove(axis & !still_held);
+        }
+        self.set_stick_sim(stick_sim);
+        true
+    }
+
+    /// Yields the buttons held through keys mapped to them.
+    fn key_buttons(&self) -> GamepadButton {
+        self.held_keys.iter()
+            .filter_map(|key| self.mappings.get(key.as_str()))
+            .fold(GamepadButton::NONE, |held, button| held | *button)
+    }
+
+    /// Keeps track of which mapped keys are held.
+    fn hold_key(&mut self, key: &str, state: bool) {
+        if state {
+            self.held_keys.insert(String::from(key));
+        } else {
+            self.held_keys.remove(key);
+        }
+    }
+
+    /// Sets how long, in seconds, simulated sticks take to go from
+    /// rest to fully tilted (and back). Zero disables ramping.
+    pub fn set_stick_ramp(&mut self, time: f64) {
+        self.stick_ramp = time.max(0.0);
+    }
+
+    /// Advances time-dependent state, such as ramping simulated sticks
+    /// and button hold times, and takes a snapshot of the buttons so
+    /// that presses and releases can be detected on the next frame.
+    /// # Arguments
+    /// * `dt` - Time elapsed since last update, in seconds.
+    pub fn update(&mut self, dt: f64) {
+        for (bit, time) in self.hold_times.iter_mut().enumerate() {
+            if self.buttons & (1 << bit) != 0 {
+                *time += dt;
+            } else {
+                *time = 0.0;
+            }
+        }
+        self.prev_buttons = self.buttons;
+
+        if self.stick_ramp > 0.0 {
+            let max_delta = dt / self.stick_ramp;
+            let (lstick, rstick) = self.stick_sim_targets();
+            self.sim_sticks = [approach(self.sim_sticks[0], lstick, max_delta),
+                               approach(self.sim_sticks[1], rstick, max_delta)];
+            self.combine_sticks();
+        }
+    }
+
+    /// Picks the value of each stick, from either the physical
+    /// gamepad or the simulated stick.
+    fn combine_sticks(&mut self) {
+        let pick = |pad: Vector2, sim: Vector2| if pad != Vector2::zero() { pad } else { sim };
+        self.lstick = pick(self.pad_sticks[0], self.sim_sticks[0]);
+        self.rstick = pick(self.pad_sticks[1], self.sim_sticks[1]);
+    }
+
+    /// Converts the simulated stick flags into stick vectors.
+    /// Diagonals are normalized, so that they are as long as the
+    /// straight directions.
+    fn stick_sim_targets(&self) -> (Vector2, Vector2) {
+        let sim = self.stick_sim;
+        let axis = |up, down, left, right| {
+            let value = |flag| if sim.contains(flag) { 1.0 } else { 0.0 };
+            Vector2::new(value(right) - value(left),
+                         value(down) - value(up)).normalized()
+        };
+
+        (axis(GamepadAxisSim::L_UP, GamepadAxisSim::L_DWN,
+              GamepadAxisSim::L_LFT, GamepadAxisSim::L_RGT),
+         axis(GamepadAxisSim::R_UP, GamepadAxisSim::R_DWN,
+              GamepadAxisSim::R_LFT, GamepadAxisSim::R_RGT))
     }
 
 
@@ -114,12 +307,69 @@ impl GamepadState {
         }
     }
 
+    pub fn map_axis(&mut self, key: &'static str, axis: GamepadAxisSim) {
+        if axis != GamepadAxisSim::NONE {
+            self.axis_mappings.insert(key, axis);
+        }
+    }
+
+    /// Maps the default keyboard bindings: arrows and WASD to the
+    /// D-pad and to the left stick, Space to A and Enter to START.
+    /// Keys are named after `KeyboardEvent.key`, with letters in
+    /// lowercase.
+    pub fn map_default_keys(&mut self) {
+        self.map_button("ArrowUp",    GamepadButton::D_UP);
+        self.map_button("ArrowDown",  GamepadButton::D_DWN);
+        self.map_button("ArrowLeft",  GamepadButton::D_LFT);
+        self.map_button("ArrowRight", GamepadButton::D_RGT);
+
+        self.map_button("w", GamepadButton::D_UP);
+        self.map_button("s", GamepadButton::D_DWN);
+        self.map_button("a", GamepadButton::D_LFT);
+        self.map_button("d", GamepadButton::D_RGT);
+
+        self.map_button(" ",     GamepadButton::A);
+        self.map_button("Enter", GamepadButton::START);
+
+        for &(arrow, letter, axis) in &[("ArrowUp",    "w", GamepadAxisSim::L_UP),
+                                        ("ArrowDown",  "s", GamepadAxisSim::L_DWN),
+                                        ("ArrowLeft",  "a", GamepadAxisSim::L_LFT),
+                                        ("ArrowRight", "d", GamepadAxisSim::L_RGT)] {
+            self.map_axis(arrow, axis);
+            self.map_axis(letter, axis);
+        }
+    }
+
     // Getters
     pub fn left_stick(&self) -> Vector2 { self.lstick }
     pub fn right_stick(&self) -> Vector2 { self.rstick }
     pub fn button_pressed(&self, button: GamepadButton) -> bool {
         self.buttons & button.bits != 0
     }
+
+    /// Whether a button went down since the last frame.
+    pub fn just_pressed(&self, button: GamepadButton) -> bool {
+        self.buttons & !self.prev_buttons & button.bits != 0
+    }
+
+    /// Whether a button went up since the last frame.
+    pub fn just_released(&self, button: GamepadButton) -> bool {
+        !self.buttons & self.prev_buttons & button.bits != 0
+    }
+
+    /// Yields for how long, in seconds, a button has been held, not
+    /// counting the current frame. Yields zero if it is not held.
+    /// If more than one button is given, yields the shortest time.
+    pub fn held_for(&self, button: GamepadButton) -> f64 {
+        if button == GamepadButton::NONE || self.buttons & button.bits != button.bits {
+            return 0.0;
+        }
+
+        self.hold_times.iter()
+            .enumerate()
+            .filter(|&(bit, _)| button.bits & (1 << bit) != 0)
+            .fold(::std::f64::INFINITY, |shortest, (_, time)| shortest.min(*time))
+    }
 }
 
 
@@ -138,4 +388,172 @@ impl InputState {
             gamepad: GamepadState::new(),
         }
     }
+
+    /// Advances time-dependent input state. Should be called
+    /// once per logic step.
+    /// # Arguments
+    /// * `dt` - Time elapsed since last update, in seconds.
+    pub fn update(&mut self, dt: f64) {
+        self.gamepad.update(dt);
+        self.mouse.update();
+    }
+}
+
+/// Moves a vector towards a target, without moving farther than
+/// a maximum distance.
+fn approach(from: Vector2, to: Vector2, max_delta: f64) -> Vector2 {
+    let delta = to - from;
+    let distance = delta.length();
+    if distance <= max_delta {
+        to
+    } else {
+        from + delta * (max_delta / distance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_sharing_a_button_release_it_together() {
+        let mut gamepad = GamepadState::new();
+        gamepad.map_default_keys();
+
+        assert!(gamepad.set_button_mapped("ArrowUp", true));
+        assert!(gamepad.set_button_mapped("w", true));
+        assert!(gamepad.set_button_mapped("ArrowUp", false));
+        assert!(gamepad.button_pressed(GamepadButton::D_UP));
+
+        assert!(gamepad.set_button_mapped("w", false));
+        assert!(!gamepad.button_pressed(GamepadButton::D_UP));
+    }
+
+    #[test]
+    fn keys_sharing_a_direction_release_it_together() {
+        let mut gamepad = GamepadState::new();
+        gamepad.map_default_keys();
+
+        assert!(gamepad.set_axis_mapped("ArrowLeft", true));
+        assert!(gamepad.set_axis_mapped("a", true));
+        assert!(gamepad.set_axis_mapped("d", true));
+        assert!(gamepad.set_axis_mapped("a", false));
+        assert!(gamepad.set_axis_mapped("d", false));
+        assert_eq!(gamepad.left_stick(), Vector2::new(-1.0, 0.0));
+
+        assert!(gamepad.set_axis_mapped("ArrowLeft", false));
+        assert_eq!(gamepad.left_stick(), Vector2::zero());
+    }
+
+    #[test]
+    fn unmapped_keys_are_ignored() {
+        let mut gamepad = GamepadState::new();
+        gamepad.map_default_keys();
+
+        assert!(!gamepad.set_button_mapped("q", true));
+        assert!(!gamepad.set_axis_mapped("q", true));
+        assert_eq!(gamepad.buttons, 0);
+        assert_eq!(gamepad.left_stick(), Vector2::zero());
+    }
+
+    #[test]
+    fn gamepad_presses_and_releases_last_one_step() {
+        let mut gamepad = GamepadState::new();
+
+        gamepad.set_button(GamepadButton::A, true);
+        assert!(gamepad.just_pressed(GamepadButton::A));
+        assert_eq!(gamepad.held_for(GamepadButton::A), 0.0);
+
+        for step in 1..4 {
+            gamepad.update(0.25);
+            assert!(!gamepad.just_pressed(GamepadButton::A));
+            assert!(!gamepad.just_released(GamepadButton::A));
+            assert_eq!(gamepad.held_for(GamepadButton::A), 0.25 * step as f64);
+        }
+
+        gamepad.set_button(GamepadButton::A, false);
+        assert!(gamepad.just_released(GamepadButton::A));
+        assert_eq!(gamepad.held_for(GamepadButton::A), 0.0);
+
+        gamepad.update(0.25);
+        assert!(!gamepad.just_released(GamepadButton::A));
+        assert_eq!(gamepad.held_for(GamepadButton::A), 0.0);
+
+        gamepad.set_button(GamepadButton::A, true);
+        gamepad.update(0.25);
+        assert_eq!(gamepad.held_for(GamepadButton::A), 0.25);
+    }
+
+    #[test]
+    fn mouse_presses_and_releases_last_one_step() {
+        let mut mouse = MouseState::new();
+
+        mouse.set_button(MouseButton::LEFT, true);
+        assert!(mouse.just_pressed(MouseButton::LEFT));
+        assert!(!mouse.just_released(MouseButton::LEFT));
+
+        mouse.update();
+        assert!(mouse.button_pressed(MouseButton::LEFT));
+        assert!(!mouse.just_pressed(MouseButton::LEFT));
+
+        mouse.set_button(MouseButton::LEFT, false);
+        assert!(mouse.just_released(MouseButton::LEFT));
+        assert!(!mouse.just_pressed(MouseButton::LEFT));
+
+        mouse.update();
+        assert!(!mouse.just_released(MouseButton::LEFT));
+    }
+
+    #[test]
+    fn simulated_sticks_ramp_up_and_down() {
+        let mut gamepad = GamepadState::new();
+        gamepad.map_default_keys();
+        gamepad.set_stick_ramp(0.5);
+
+        assert!(gamepad.set_axis_mapped("d", true));
+        assert_eq!(gamepad.left_stick(), Vector2::zero());
+        for step in 1..5 {
+            gamepad.update(0.125);
+            let length = gamepad.left_stick().length();
+            assert!((length - 0.25 * step as f64).abs() < 1e-9, "step {}: {}", step, length);
+        }
+        assert_eq!(gamepad.left_stick(), Vector2::new(1.0, 0.0));
+
+        gamepad.update(0.125);
+        assert_eq!(gamepad.left_stick(), Vector2::new(1.0, 0.0));
+
+        assert!(gamepad.set_axis_mapped("d", false));
+        for step in 1..5 {
+            gamepad.update(0.125);
+            let length = gamepad.left_stick().length();
+            assert!((length - (1.0 - 0.25 * step as f64)).abs() < 1e-9, "step {}: {}", step, length);
+        }
+        assert_eq!(gamepad.left_stick(), Vector2::zero());
+    }
+
+    #[test]
+    fn simulated_diagonals_are_normalized() {
+        let mut gamepad = GamepadState::new();
+        gamepad.map_default_keys();
+
+        assert!(gamepad.set_axis_mapped("d", true));
+        assert!(gamepad.set_axis_mapped("s", true));
+        let stick = gamepad.left_stick();
+        assert!((stick.length() - 1.0).abs() < 1e-9);
+        assert!((stick.x - stick.y).abs() < 1e-9);
+        assert!(stick.x > 0.0);
+
+        gamepad.set_stick_sim(GamepadAxisSim::NONE);
+        gamepad.set_stick_ramp(0.5);
+        assert!(gamepad.set_axis_mapped("d", true));
+        assert!(gamepad.set_axis_mapped("s", true));
+        for _ in 0..2 {
+            gamepad.update(0.125);
+        }
+        assert!((gamepad.left_stick().length() - 0.5).abs() < 1e-9);
+        for _ in 0..2 {
+            gamepad.update(0.125);
+        }
+        assert!((gamepad.left_stick().length() - 1.0).abs() < 1e-9);
+    }
 }
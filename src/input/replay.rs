@@ -0,0 +1,331 @@
+//! Submodule for recording and replaying input.
+//! A replay holds the seed of the world it was recorded on, plus the
+//! input seen on every logic step. Playing it back on a world built
+//! from the same seed reproduces the original run exactly.
+
+use types::Vector2;
+use input::InputState;
+
+/// Identifies replay files.
+const MAGIC: &[u8] = b"WPRP";
+
+/// Current version of the replay format.
+const VERSION: u8 = 1;
+
+/// Size of a single encoded run of frames, in bytes.
+const RECORD_SIZE: usize = 57;
+
+/// Most frames a replay may hold: four hours at 60 steps per
+/// second. Runs let a few bytes stand for many frames, so this keeps
+/// a corrupted file from asking for unbounded memory.
+const MAX_FRAMES: usize = 4 * 60 * 60 * 60;
+
+/// Represents the input seen on a single logic step.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameInput {
+    pub buttons:        u32,
+    pub lstick:         Vector2,
+    pub rstick:         Vector2,
+    pub mouse_position: Vector2,
+    pub mouse_buttons:  u8,
+}
+
+impl FrameInput {
+    /// Takes a snapshot of an input state.
+    pub fn capture(input: &InputState) -> Self {
+        FrameInput {
+            buttons:        input.gamepad.buttons,
+            lstick:         input.gamepad.lstick,
+            rstick:         input.gamepad.rstick,
+            mouse_position: input.mouse.position,
+            mouse_buttons:  input.mouse.buttons,
+        }
+    }
+
+    /// Overwrites an input state with this snapshot.
+    pub fn apply(&self, input: &mut InputState) {
+        input.gamepad.buttons = self.buttons;
+        input.gamepad.lstick  = self.lstick;
+        input.gamepad.rstick  = self.rstick;
+        input.mouse.position  = self.mouse_position;
+        input.mouse.buttons   = self.mouse_buttons;
+    }
+}
+
+
+/// Represents a recorded run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    /// Seed of the world's random number generator.
+    pub seed:   u64,
+    /// Duration of each logic step, in seconds.
+    pub dt:     f64,
+    /// Input of each logic step, in order.
+    pub frames: Vec<FrameInput>,
+}
+
+impl Replay {
+    /// Creates a new, empty replay.
+    /// # Arguments
+    /// * `seed` - Seed of the world being recorded.
+    pub fn new(seed: u64) -> Self {
+        Replay {
+            seed:   seed,
+            dt:     0.0,
+            frames: vec![],
+        }
+    }
+
+    /// Appends a logic step to the replay.
+    /// # Arguments
+    /// * `frame` - Input seen on the step.
+    ///
+    /// * `dt` - Duration of the step. Replays assume a fixed timestep,
+    /// so only the first duration is kept.
+    pub fn record(&mut self, frame: FrameInput, dt: f64) {
+        if self.frames.is_empty() {
+            self.dt = dt;
+        }
+        self.frames.push(frame);
+    }
+
+    /// Encodes the replay in its binary format. Repeated frames are
+    /// stored only once, along with how many times they repeat.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::from(MAGIC);
+        bytes.push(VERSION);
+        write_u64(&mut bytes, self.seed);
+        write_u64(&mut bytes, self.dt.to_bits());
+        write_u32(&mut bytes, self.frames.len() as u32);
+
+        let mut index = 0;
+        while index < self.frames.len() {
+            let frame = self.frames[index];
+            let mut run = 1;
+            while index + run < self.frames.len() && self.frames[index + run] == frame {
+                run += 1;
+            }
+
+            write_u32(&mut bytes, run as u32);
+            write_u32(&mut bytes, frame.buttons);
+            for value in &[frame.lstick, frame.rstick, frame.mouse_position] {
+                write_u64(&mut bytes, value.x.to_bits());
+                write_u64(&mut bytes, value.y.to_bits());
+            }
+            bytes.push(frame.mouse_buttons);
+            index += run;
+        }
+        bytes
+    }
+
+    /// Decodes a replay from its binary format.
+    /// Yields an error string on failure, including when the replay
+    /// claims to hold more than four hours of frames.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Replay, &'static str> {
+        if bytes.len() < MAGIC.len() + 1 || &bytes[..MAGIC.len()] != MAGIC {
+            return Err("Not a replay file");
+        }
+        if bytes[MAGIC.len()] != VERSION {
+            return Err("Unsupported replay version");
+        }
+
+        let mut reader = Reader { bytes: bytes, position: MAGIC.len() + 1 };
+        let seed = reader.u64()?;
+        let dt = f64::from_bits(reader.u64()?);
+        let count = reader.u32()? as usize;
+        if count > MAX_FRAMES {
+            return Err("Replay is too long");
+        }
+
+        // Don't trust the count any further than the data at hand
+        let mut frames = Vec::with_capacity(count.min(reader.remaining() / RECORD_SIZE));
+        while frames.len() < count {
+            let run = reader.u32()? as usize;
+            let buttons = reader.u32()?;
+            let mut vectors = [Vector2::zero(); 3];
+            for vector in vectors.iter_mut() {
+                vector.x = f64::from_bits(reader.u64()?);
+                vector.y = f64::from_bits(reader.u64()?);
+            }
+            let mouse_buttons = reader.u8()?;
+
+            if run == 0 || frames.len() + run > count {
+                return Err("Corrupted replay frames");
+            }
+            let frame = FrameInput {
+                buttons:        buttons,
+                lstick:         vectors[0],
+                rstick:         vectors[1],
+                mouse_position: vectors[2],
+                mouse_buttons:  mouse_buttons,
+            };
+            for _ in 0..run {
+                frames.push(frame);
+            }
+        }
+
+        Ok(Replay {
+            seed:   seed,
+            dt:     dt,
+            frames: frames,
+        })
+    }
+}
+
+
+/// Plays a replay back, one frame at a time.
+#[derive(Clone, Debug)]
+pub struct ReplayPlayer {
+    replay:  Replay,
+    current: usize,
+}
+
+impl ReplayPlayer {
+    /// Creates a new player, starting on the first frame.
+    pub fn new(replay: Replay) -> Self {
+        ReplayPlayer {
+            replay:  replay,
+            current: 0,
+        }
+    }
+
+    /// Yields the next frame, or `None` if the replay is over.
+    pub fn next_frame(&mut self) -> Option<FrameInput> {
+        let frame = self.replay.frames.get(self.current).cloned();
+        if frame.is_some() {
+            self.current += 1;
+        }
+        frame
+    }
+
+    /// Whether every frame was already played.
+    pub fn finished(&self) -> bool {
+        self.current >= self.replay.frames.len()
+    }
+}
+
+
+// ================================
+
+fn write_u32(bytes: &mut Vec<u8>, value: u32) {
+    for i in 0..4 {
+        bytes.push((value >> (i * 8)) as u8);
+    }
+}
+
+fn write_u64(bytes: &mut Vec<u8>, value: u64) {
+    for i in 0..8 {
+        bytes.push((value >> (i * 8)) as u8);
+    }
+}
+
+/// Reads little-endian numbers from a byte slice.
+struct Reader<'a> {
+    bytes:    &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.position
+    }
+
+    fn take(&mut self, amount: usize) -> Result<&'a [u8], &'static str> {
+        if self.position + amount > self.bytes.len() {
+            return Err("Unexpected end of replay");
+        }
+        let slice = &self.bytes[self.position..self.position + amount];
+        self.position += amount;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, &'static str> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, &'static str> {
+        let slice = self.take(4)?;
+        Ok(slice.iter().rev().fold(0, |value, byte| (value << 8) | *byte as u32))
+    }
+
+    fn u64(&mut self) -> Result<u64, &'static str> {
+        let slice = self.take(8)?;
+        Ok(slice.iter().rev().fold(0, |value, byte| (value << 8) | *byte as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use input::GamepadButton;
+    use platform::headless::Headless;
+
+    const DT: f64 = 1.0 / 60.0;
+
+    fn frame(buttons: GamepadButton) -> FrameInput {
+        FrameInput {
+            buttons:        buttons.bits,
+            lstick:         Vector2::zero(),
+            rstick:         Vector2::new(0.25, -0.5),
+            mouse_position: Vector2::new(10.0, 20.0),
+            mouse_buttons:  1,
+        }
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let mut replay = Replay::new(77);
+        for index in 0..50 {
+            let buttons = if index % 7 < 3 { GamepadButton::A } else { GamepadButton::NONE };
+            replay.record(frame(buttons), DT);
+        }
+
+        let bytes = replay.to_bytes();
+        assert_eq!(Replay::from_bytes(&bytes), Ok(replay));
+        assert!(Replay::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn corrupted_counts_are_rejected() {
+        let mut replay = Replay::new(1);
+        replay.record(frame(GamepadButton::NONE), DT);
+        let mut bytes = replay.to_bytes();
+
+        // The frame count follows the magic, version, seed and dt
+        let count = MAGIC.len() + 1 + 16;
+        for byte in &mut bytes[count..count + 4] {
+            *byte = 0xff;
+        }
+        assert_eq!(Replay::from_bytes(&bytes), Err("Replay is too long"));
+
+        // Counts within the limit still need their frames
+        bytes[count..count + 4].copy_from_slice(&[0x00, 0x10, 0x00, 0x00]);
+        assert_eq!(Replay::from_bytes(&bytes), Err("Unexpected end of replay"));
+    }
+
+    #[test]
+    fn replays_reproduce_the_players_final_position() {
+        // Run right, then jump while still running
+        let mut live = Headless::new(Vector2::new(320.0, 180.0), 800.0, 9);
+        live.world.start_recording().unwrap();
+        for index in 0..180 {
+            let mut buttons = GamepadButton::D_RGT;
+            // Jump from frame 90 up to frame 110
+            let jumping = index - 90;
+            if jumping >= 0 && jumping < 20 {
+                buttons |= GamepadButton::A;
+            }
+            live.world.input.borrow_mut().gamepad.buttons = buttons.bits;
+            live.step(DT);
+        }
+        let replay = live.world.stop_recording().unwrap();
+        let expected = live.position_of(0).unwrap();
+        assert!(expected.x > 400.0);
+
+        let replay = Replay::from_bytes(&replay.to_bytes()).unwrap();
+        let mut played = Headless::from_replay(Vector2::new(320.0, 180.0), 800.0, &replay);
+        assert_eq!(played.run_replay(&replay), 180);
+        assert_eq!(played.position_of(0), Some(expected));
+        assert_eq!(played.positions(), live.positions());
+    }
+}
@@ -1,19 +1,27 @@
 #![recursion_limit="2048"]
+#![cfg_attr(test, feature(test))]
 
+#[cfg(target_arch = "wasm32")]
 #[macro_use]
 extern crate stdweb;
-extern crate ref_eq;
+#[cfg(test)]
+extern crate test;
 #[macro_use]
 extern crate bitflags;
 
+#[cfg(target_arch = "wasm32")]
 use stdweb::unstable::TryInto;
+#[cfg(target_arch = "wasm32")]
 use stdweb::traits::IMouseEvent;
+#[cfg(target_arch = "wasm32")]
 use stdweb::web::html_element::CanvasElement;
+#[cfg(target_arch = "wasm32")]
 use stdweb::web::{
     self,
     IEventTarget,
     INonElementParentNode
 };
+#[cfg(target_arch = "wasm32")]
 use stdweb::web::event::{
     IEvent,
     IKeyboardEvent,
@@ -34,57 +42,85 @@ pub mod types;     // Vectors, matrices, etc
 pub mod collision; // Bounding volumes, collision, partitioning, etc
 pub mod render;    // Rendering, textures, animation, etc
 pub mod input;     // General game I/O, etc
+pub mod platform;  // Clocks, headless harness, etc
 pub mod game;      // Actual specific game objects (Entity, etc)
 
+#[cfg(target_arch = "wasm32")]
 use game::world::World;
+#[cfg(target_arch = "wasm32")]
 use render::Renderer2D;
+#[cfg(target_arch = "wasm32")]
+use render::canvas::CanvasBackend;
+#[cfg(target_arch = "wasm32")]
+use platform::Clock;
+#[cfg(target_arch = "wasm32")]
+use platform::web::JsClock;
+#[cfg(target_arch = "wasm32")]
+use common::timestep::FixedTimestep;
+#[cfg(target_arch = "wasm32")]
+use input::InputState;
+#[cfg(target_arch = "wasm32")]
+use common::objects::ObjectRef;
+#[cfg(target_arch = "wasm32")]
+use std::rc::Rc;
 
 
 /// Handles keyboard events.
-fn on_key(_key: &str, location: KeyboardLocation, _is_pressed: bool) -> bool {
+/// Yields whether the key is bound to anything, so that the browser
+/// does not act on it (e.g. scrolling the page with the arrows).
+#[cfg(target_arch = "wasm32")]
+fn on_key(input: &ObjectRef<InputState>, key: &str, location: KeyboardLocation, is_pressed: bool) -> bool {
     let _location = format!("{:?}", location);
-    true
+
+    // Letters are bound in lowercase, regardless of Shift or Caps Lock
+    let key = if key.chars().count() == 1 { key.to_lowercase() } else { String::from(key) };
+    let mut input = input.borrow_mut();
+    let is_button = input.gamepad.set_button_mapped(&key, is_pressed);
+    let is_axis = input.gamepad.set_axis_mapped(&key, is_pressed);
+    is_button || is_axis
 }
 
 
 /// Handles mouse presses (up and down).
-fn on_mouse_click(btn: MouseButton, _is_pressed: bool, _pos: (f64, f64)) -> bool {
-    let _btn = format!("{:?}", btn);
+#[cfg(target_arch = "wasm32")]
+fn on_mouse_click(input: &ObjectRef<InputState>, btn: MouseButton, is_pressed: bool, pos: (f64, f64)) -> bool {
+    let button = match btn {
+        MouseButton::Left   => input::MouseButton::LEFT,
+        MouseButton::Wheel  => input::MouseButton::MID,
+        MouseButton::Right  => input::MouseButton::RIGHT,
+        _ => input::MouseButton::NONE,
+    };
+
+    let mut input = input.borrow_mut();
+    input.mouse.position = types::Vector2::new(pos.0, pos.1);
+    input.mouse.set_button(button, is_pressed);
     true
 }
 
 /// Handles sole mouse movement, without presses.
-fn on_mouse_move(_pos: (f64, f64)) -> bool {
+#[cfg(target_arch = "wasm32")]
+fn on_mouse_move(input: &ObjectRef<InputState>, pos: (f64, f64)) -> bool {
+    input.borrow_mut().mouse.position = types::Vector2::new(pos.0, pos.1);
     true
 }
 
-fn get_current_time() -> u64 {
-    // Oops. Following works only for desktop.
-    /*
-    use std::time::{ SystemTime, UNIX_EPOCH };
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap();
-    now.as_secs() * 1000 +
-    now.subsec_nanos() as u64 / 1_000_000*/
-
-    // The following works OK for WASM, though it relies on JS.
-    let now: u64 = js! { return Date.now() }.try_into().unwrap();
-    now
-}
-
-fn semi_loop(mut world: World, last_time: u64) {
-    let current_time = get_current_time();
+#[cfg(target_arch = "wasm32")]
+fn semi_loop(mut world: World, clock: Rc<Clock>, mut timestep: FixedTimestep, last_time: u64) {
+    let current_time = clock.now();
     let delta = (current_time - last_time) as f64 / 1000.0;
-    world.game_step(delta);
+    for _ in 0..timestep.advance(delta) {
+        world.update(timestep.step());
+    }
+    world.render(timestep.alpha());
 
     web::window().request_animation_frame(move |_| {
-        semi_loop(world.clone(), current_time);
+        semi_loop(world.clone(), clock, timestep, current_time);
     });
 }
 
 
 
+#[cfg(target_arch = "wasm32")]
 fn main() {
     stdweb::initialize();
 
@@ -97,16 +133,21 @@ fn main() {
     canvas.set_height(360);
 
     // Create renderer
-    let renderer: Renderer2D = Renderer2D::new(&canvas);
+    let renderer: Renderer2D = Renderer2D::new(Rc::new(CanvasBackend::new(&canvas)));
 
-    // Create world
-    let mut world: World = World::new(renderer, 800.0);
+    // Create world, seeding it with the startup time
+    let clock: Rc<Clock> = Rc::new(JsClock);
+    let mut world: World = World::new(renderer, 800.0, clock.now());
     world.init();
 
     // Bind common keys
+    world.input.borrow_mut().gamepad.map_default_keys();
+
+    // Read the first gamepad, if any
     {
-        use input::GamepadButton;
-        world.input.gamepad.map_button("s", GamepadButton::A);
+        use input::devices::{ BrowserGamepad, DeadZones, GamepadPoller };
+        world.connect_gamepad(GamepadPoller::new(Box::new(BrowserGamepad::new(0)),
+                                                 DeadZones::default()));
     }
     
 
@@ -114,45 +155,78 @@ fn main() {
     // === Event bindings ===
     
     // Keyboard
-    web::window().add_event_listener(|event: KeyDownEvent| {
-        if on_key(&event.key(), event.location(), true) {
-            event.prevent_default();
-        }
-    });
+    {
+        let input = world.input.clone();
+        web::window().add_event_listener(move |event: KeyDownEvent| {
+            if on_key(&input, &event.key(), event.location(), true) {
+                event.prevent_default();
+            }
+        });
+    }
 
-    web::window().add_event_listener(|event: KeyUpEvent| {
-        if on_key(&event.key(), event.location(), false) {
-            event.prevent_default();
-        }
-    });
+    {
+        let input = world.input.clone();
+        web::window().add_event_listener(move |event: KeyUpEvent| {
+            if on_key(&input, &event.key(), event.location(), false) {
+                event.prevent_default();
+            }
+        });
+    }
 
 
     
     // Mouse
-    web::window().add_event_listener(|event: MouseDownEvent| {
-        if on_mouse_click(event.button(), true, (event.client_x() as f64,
-                                                 event.client_y() as f64)) {
-            event.prevent_default();
-        }
-    });
+    {
+        let input = world.input.clone();
+        web::window().add_event_listener(move |event: MouseDownEvent| {
+            if on_mouse_click(&input, event.button(), true, (event.client_x() as f64,
+                                                             event.client_y() as f64)) {
+                event.prevent_default();
+            }
+        });
+    }
 
-    web::window().add_event_listener(|event: MouseUpEvent| {
-        if on_mouse_click(event.button(), false, (event.client_x() as f64,
-                                                  event.client_y() as f64)) {
-            event.prevent_default();
-        }
-    });
+    {
+        let input = world.input.clone();
+        web::window().add_event_listener(move |event: MouseUpEvent| {
+            if on_mouse_click(&input, event.button(), false, (event.client_x() as f64,
+                                                              event.client_y() as f64)) {
+                event.prevent_default();
+            }
+        });
+    }
 
-    web::window().add_event_listener(|event: MouseMoveEvent| {
-        if on_mouse_move((event.client_x() as f64, event.client_y() as f64)) {
-            event.prevent_default();
-        }
-    });
+    {
+        let input = world.input.clone();
+        web::window().add_event_listener(move |event: MouseMoveEvent| {
+            if on_mouse_move(&input, (event.client_x() as f64, event.client_y() as f64)) {
+                event.prevent_default();
+            }
+        });
+    }
 
     // Errr, hmmm, game loop.
+    let timestep = FixedTimestep::default();
     web::window().request_animation_frame(move |_| {
-        semi_loop(world.clone(), get_current_time());
+        let now = clock.now();
+        semi_loop(world.clone(), clock, timestep, now);
     });
     
     stdweb::event_loop();
 }
+
+/// Native builds have no browser to run on, so they run the
+/// world headlessly for a few seconds and report where everything
+/// ended up.
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    use platform::headless::Headless;
+    use types::Vector2;
+
+    let mut headless = Headless::new(Vector2::new(640.0, 360.0), 800.0, 0);
+    headless.run(600, 1.0 / 60.0);
+
+    for (id, position) in headless.positions() {
+        println!("Object #{} at ({}, {})", id, position.x, position.y);
+    }
+}
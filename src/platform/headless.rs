@@ -0,0 +1,229 @@
+//! Submodule for running the game without a browser.
+//! Provides a stand-in for the clock, plus a harness which builds
+//! a `World` on top of a software renderer and steps it by hand.
+
+use types::Vector2;
+use platform::Clock;
+use render::Renderer2D;
+use render::software::SoftwareBackend;
+use game::world::World;
+use input::replay::Replay;
+use common::timestep::FixedTimestep;
+use std::cell::Cell;
+use std::rc::Rc;
+
+/// Clock which only moves when told to.
+pub struct ManualClock {
+    now: Cell<u64>,
+}
+
+impl ManualClock {
+    /// Creates a new clock starting at a given time.
+    /// # Arguments
+    /// * `start` - Starting time, in milliseconds.
+    pub fn new(start: u64) -> Self {
+        ManualClock { now: Cell::new(start) }
+    }
+
+    /// Moves the clock forward.
+    /// # Arguments
+    /// * `ms` - Amount of milliseconds to advance.
+    pub fn advance(&self, ms: u64) {
+        self.now.set(self.now.get() + ms);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.now.get()
+    }
+}
+
+/// Harness for stepping a `World` natively.
+pub struct Headless {
+    pub world: World,
+    backend:   Rc<SoftwareBackend>,
+    clock:     ManualClock,
+    timestep:  FixedTimestep,
+    frames:    u64,
+}
+
+impl Headless {
+    /// Creates and initializes a new headless world.
+    /// # Arguments
+    /// * `viewport` - Size of the software framebuffer.
+    ///
+    /// * `world_max_size` - Size of the world, as given to `World::new`.
+    ///
+    /// * `seed` - Seed for the world's random number generator.
+    pub fn new(viewport: Vector2, world_max_size: f64, seed: u64) -> Self {
+        let backend = Rc::new(SoftwareBackend::new(viewport.x as usize,
+                                                   viewport.y as usize));
+        let renderer = Renderer2D::new(backend.clone());
+        let mut world = World::new(renderer, world_max_size, seed);
+        world.init();
+
+        Headless {
+            world:    world,
+            backend:  backend,
+            clock:    ManualClock::new(0),
+            timestep: FixedTimestep::default(),
+            frames:   0,
+        }
+    }
+
+    /// Creates and initializes a new headless world which plays a
+    /// replay back.
+    /// # Arguments
+    /// * `viewport` - Size of the software framebuffer.
+    ///
+    /// * `world_max_size` - Size of the world, as given to `World::new`.
+    ///
+    /// * `replay` - Replay to be played back. Its seed is used for the world.
+    pub fn from_replay(viewport: Vector2, world_max_size: f64, replay: &Replay) -> Self {
+        let mut headless = Headless::new(viewport, world_max_size, replay.seed);
+        headless.world.start_playback(replay.clone());
+        headless
+    }
+
+    /// Steps the world until the replay being played back is over,
+    /// using the replay's own step duration.
+    /// Yields the amount of frames stepped.
+    pub fn run_replay(&mut self, replay: &Replay) -> u32 {
+        let mut frames = 0;
+        while self.world.is_playing_back() {
+            self.step(replay.dt);
+            frames += 1;
+        }
+        frames
+    }
+
+    /// Advances the world by a single logic step, then draws it.
+    /// This doesn't go through a `FixedTimestep`: `dt` already is
+    /// the duration of one fixed step, and replays need exactly one
+    /// update per recorded frame. Use `advance` to run frames of
+    /// arbitrary duration through the game's own loop instead.
+    /// # Arguments
+    /// * `dt` - Frame duration, in seconds.
+    pub fn step(&mut self, dt: f64) {
+        self.clock.advance((dt * 1000.0).round() as u64);
+        self.world.game_step(dt);
+        self.frames += 1;
+    }
+
+    /// Advances the world by a number of frames of fixed duration.
+    /// # Arguments
+    /// * `frames` - Amount of frames to run.
+    ///
+    /// * `dt` - Duration of each frame, in seconds.
+    pub fn run(&mut self, frames: u32, dt: f64) {
+        for _ in 0..frames {
+            self.step(dt);
+        }
+    }
+
+    /// Advances the world by a frame of arbitrary duration, the
+    /// same way the browser's game loop does: as many fixed logic
+    /// steps as fit in the time, then a render interpolated between
+    /// the last two. Yields the amount of logic steps run.
+    /// # Arguments
+    /// * `frame_time` - Time elapsed since the last frame, in seconds.
+    pub fn advance(&mut self, frame_time: f64) -> u32 {
+        self.clock.advance((frame_time * 1000.0).round() as u64);
+        let steps = self.timestep.advance(frame_time);
+        for _ in 0..steps {
+            self.world.update(self.timestep.step());
+        }
+        self.world.render(self.timestep.alpha());
+        self.frames += 1;
+        steps
+    }
+
+    /// Yields the amount of frames stepped so far.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Yields the clock driven by this harness.
+    pub fn clock(&self) -> &ManualClock {
+        &self.clock
+    }
+
+    /// Yields the framebuffer the world is drawing to.
+    pub fn backend(&self) -> &SoftwareBackend {
+        &self.backend
+    }
+
+    /// Yields the ids and positions of every object in the world.
+    pub fn positions(&self) -> Vec<(u32, Vector2)> {
+        self.world.object_positions()
+    }
+
+    /// Yields the position of a single object.
+    /// # Arguments
+    /// * `id` - Id of the object.
+    pub fn position_of(&self, id: u32) -> Option<Vector2> {
+        self.positions().into_iter()
+            .find(|pair| pair.0 == id)
+            .map(|pair| pair.1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f64 = 1.0 / 60.0;
+
+    fn headless(seed: u64) -> Headless {
+        Headless::new(Vector2::new(320.0, 180.0), 800.0, seed)
+    }
+
+    #[test]
+    fn stepping_moves_the_player_onto_the_floor() {
+        let mut headless = headless(1);
+        let start = headless.position_of(0).unwrap();
+        let objects = headless.positions().len();
+
+        headless.run(120, DT);
+        let landed = headless.position_of(0).unwrap();
+        assert_eq!(headless.frames(), 120);
+        assert_eq!(headless.positions().len(), objects);
+        assert!(landed.y > start.y);
+
+        // Nothing is pressed, so it stays there
+        headless.run(60, DT);
+        assert_eq!(headless.position_of(0), Some(landed));
+        assert_eq!(headless.position_of(12345), None);
+    }
+
+    #[test]
+    fn same_seed_yields_same_positions() {
+        let mut first = headless(42);
+        let mut second = headless(42);
+        first.run(90, DT);
+        second.run(90, DT);
+        assert_eq!(first.positions(), second.positions());
+
+        let mut other = headless(43);
+        other.run(90, DT);
+        assert!(first.positions() != other.positions());
+    }
+
+    #[test]
+    fn advancing_runs_fixed_steps() {
+        let mut stepped = headless(5);
+        stepped.run(60, DT);
+
+        // Frames of 30 Hz take two steps each
+        let mut advanced = headless(5);
+        let steps: u32 = (0..30).map(|_| advanced.advance(2.0 * DT)).sum();
+        assert_eq!(steps, 60);
+        assert_eq!(advanced.frames(), 30);
+        assert_eq!(advanced.clock().now(), 30 * 33);
+        assert_eq!(advanced.positions(), stepped.positions());
+
+        // Long pauses are only partly caught up on
+        assert_eq!(advanced.advance(10.0), 5);
+    }
+}
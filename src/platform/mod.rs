@@ -0,0 +1,13 @@
+//! Module for platform services the engine depends on, such as
+//! clocks. Browser builds get them from JavaScript; native builds
+//! (and tests) get stand-ins which can be driven by hand.
+
+pub mod headless;
+#[cfg(target_arch = "wasm32")]
+pub mod web;
+
+/// Common trait for anything that can tell the current time.
+pub trait Clock {
+    /// Yields the current time, in milliseconds.
+    fn now(&self) -> u64;
+}
@@ -0,0 +1,14 @@
+//! Submodule for platform services provided by the browser.
+
+use platform::Clock;
+use stdweb::unstable::TryInto;
+
+/// Clock backed by JavaScript's `Date.now()`.
+pub struct JsClock;
+
+impl Clock for JsClock {
+    fn now(&self) -> u64 {
+        let now: u64 = js! { return Date.now() }.try_into().unwrap();
+        now
+    }
+}
@@ -0,0 +1,106 @@
+//! Submodule for rendering on a browser canvas.
+
+use types::Vector2;
+use render::RenderBackend;
+use stdweb::web::html_element::{ CanvasElement, ImageElement };
+use stdweb::web::{ CanvasRenderingContext2d, FillRule };
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+/// Render backend which draws on a HTML5 canvas, through its
+/// 2D context.
+pub struct CanvasBackend {
+    ctx:    CanvasRenderingContext2d,
+    sz:     Vector2,
+    images: RefCell<HashMap<String, ImageElement>>,
+}
+
+impl CanvasBackend {
+    /// Creates a new canvas backend.
+    /// # Arguments
+    /// `canvas` - Reference to the canvas element where the context lives.
+    pub fn new(canvas: &CanvasElement) -> Self {
+        CanvasBackend {
+            ctx:    canvas.get_context().unwrap(),
+            sz:     Vector2 { x: canvas.width() as f64, y: canvas.height() as f64 },
+            images: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Yields the image element for a given source. Images are
+    /// loaded on first use and cached afterwards.
+    fn image(&self, source: &str) -> ImageElement {
+        let mut images = self.images.borrow_mut();
+        if let Some(img) = images.get(source) {
+            return img.clone();
+        }
+
+        let img = ImageElement::new();
+        img.set_src(source);
+        images.insert(String::from(source), img.clone());
+        img
+    }
+}
+
+impl RenderBackend for CanvasBackend {
+    fn size(&self) -> Vector2 {
+        self.sz
+    }
+
+    fn draw_box(&self, color: &str, pos: Vector2, size: Vector2) {
+        self.ctx.set_fill_style_color(color);
+        self.ctx.fill_rect(pos.x, pos.y, size.x, size.y);
+    }
+
+    fn draw_circle(&self, color: &str, pos: Vector2, radius: f64) {
+        self.ctx.begin_path();
+        self.ctx.set_fill_style_color(color);
+        self.ctx.arc(pos.x, pos.y, radius, 0.0, PI * 2.0, false);
+        self.ctx.fill(FillRule::NonZero);
+        self.ctx.close_path();
+    }
+
+    fn draw_polygon(&self, color: &str, points: &[Vector2]) {
+        if points.is_empty() { return; }
+
+        self.ctx.begin_path();
+        self.ctx.set_fill_style_color(color);
+        self.ctx.move_to(points[0].x, points[0].y);
+        for point in &points[1..] {
+            self.ctx.line_to(point.x, point.y);
+        }
+        self.ctx.close_path();
+        self.ctx.fill(FillRule::NonZero);
+    }
+
+    fn draw_image(&self, source: &str, pos: Vector2) {
+        let _ = self.ctx.draw_image(self.image(source), pos.x, pos.y);
+    }
+
+    fn draw_tile(&self, source: &str, pos: Vector2, tile_size: Vector2, frame: u32) {
+        let img = self.image(source);
+
+        // Calculate frame position
+        let img_size = Vector2::new(img.width() as f64, img.height() as f64);
+        let max_x_frames = (img_size.x / tile_size.x).floor() as u32;
+
+        if max_x_frames == 0 { return; }
+
+        let cows_and_rows = Vector2::new((frame % max_x_frames) as f64,
+                                         (frame / max_x_frames) as f64);
+        
+        let frame_pos = Vector2::new(cows_and_rows.x * tile_size.x,
+                                     cows_and_rows.y * tile_size.y);
+        
+        let _ = self.ctx.draw_image_s(img,
+                                      frame_pos.x, frame_pos.y,
+                                      tile_size.x, tile_size.y,
+                                      pos.x, pos.y,
+                                      tile_size.x, tile_size.y);
+    }
+
+    fn clear(&self) {
+        self.ctx.clear_rect(0.0, 0.0, self.sz.x, self.sz.y);
+    }
+}
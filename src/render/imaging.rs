@@ -1,28 +1,25 @@
 use types::Vector2;
-use stdweb::web::html_element::ImageElement;
 use render::Renderer2D;
 use std::collections::HashMap;
 
 #[derive(Clone)]
 pub struct Texture {
-    data: ImageElement,
+    source: String,
 }
 
 impl Texture {
     pub fn from(source: &str) -> Self {
-        let data = ImageElement::new();
-        data.set_src(source);
         Texture {
-            data: data,
+            source: String::from(source),
         }
     }
 
-    fn get_data(&self) -> ImageElement {
-        self.data.clone()
+    fn get_source(&self) -> &str {
+        &self.source
     }
 
     pub fn draw(&self, renderer: &Renderer2D, pos: Vector2) {
-        renderer.draw_image_rel(self.data.clone(), pos);
+        renderer.draw_image_rel(self.get_source(), pos);
     }
 }
 
@@ -42,11 +39,11 @@ impl TextureAtlas {
     }
 
     pub fn draw_frame(&self, renderer: &Renderer2D, pos: Vector2, frame: u32) {
-        renderer.draw_tile_rel(self.texture.get_data(), pos, self.tile_size, frame);
+        renderer.draw_tile_rel(self.texture.get_source(), pos, self.tile_size, frame);
     }
 
     pub fn draw_all(&self, renderer: &Renderer2D, pos: Vector2) {
-        renderer.draw_image_rel(self.texture.get_data(), pos);
+        renderer.draw_image_rel(self.texture.get_source(), pos);
     }
 }
 
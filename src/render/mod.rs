@@ -1,44 +1,126 @@
+//! Module for rendering, textures and animation.
+
 use types::Vector2;
-use stdweb::web::html_element::{ CanvasElement, ImageElement };
-use stdweb::web::{ CanvasRenderingContext2d, FillRule };
+use collision::primitives::{ OBB, Capsule, ConvexPolygon };
+use std::rc::Rc;
 use std::f64::consts::PI;
 
 pub mod imaging;
+pub mod software;
+#[cfg(target_arch = "wasm32")]
+pub mod canvas;
+
+/// How many segments each rounded end of a capsule is drawn with.
+const CAPSULE_ARC_SEGMENTS: usize = 12;
+
+
+/// Common trait for anything that can actually put pixels
+/// somewhere. `Renderer2D` delegates all of its drawing to
+/// a backend, so game code does not need to know whether it is
+/// drawing to a browser canvas or to a plain memory buffer.
+///
+/// All positions given to a backend are in screen space; camera
+/// handling is done by `Renderer2D` itself.
+pub trait RenderBackend {
+    /// Yields the size of the drawing surface, in pixels.
+    fn size(&self) -> Vector2;
+
+    /// Draws a colored box.
+    /// # Arguments
+    /// * `color` - CSS-like color string (e.g. `"#ff00007f"`).
+    ///
+    /// * `pos` - Top-left corner of the box.
+    ///
+    /// * `size` - Width and height of the box.
+    fn draw_box(&self, color: &str, pos: Vector2, size: Vector2);
+
+    /// Draws a colored, filled circle.
+    /// # Arguments
+    /// * `color` - CSS-like color string.
+    ///
+    /// * `pos` - Center of the circle.
+    ///
+    /// * `radius` - Radius of the circle.
+    fn draw_circle(&self, color: &str, pos: Vector2, radius: f64);
+
+    /// Draws a colored, filled convex polygon.
+    /// # Arguments
+    /// * `color` - CSS-like color string.
+    ///
+    /// * `points` - Vertices of the polygon, in order.
+    fn draw_polygon(&self, color: &str, points: &[Vector2]);
+
+    /// Draws a whole image.
+    /// # Arguments
+    /// * `source` - Source path of the image.
+    ///
+    /// * `pos` - Top-left corner where the image will be drawn.
+    fn draw_image(&self, source: &str, pos: Vector2);
+
+    /// Draws a single tile of an image, treating it as a
+    /// texture atlas with tiles of a given size, laid out
+    /// row by row.
+    /// # Arguments
+    /// * `source` - Source path of the image.
+    ///
+    /// * `pos` - Top-left corner where the tile will be drawn.
+    ///
+    /// * `tile_size` - Size of a single tile.
+    ///
+    /// * `frame` - Index of the tile on the atlas.
+    fn draw_tile(&self, source: &str, pos: Vector2, tile_size: Vector2, frame: u32);
+
+    /// Clears the whole drawing surface.
+    fn clear(&self);
+}
 
 
 /// Represents a 2D renderer.
 #[derive(Clone)]
 pub struct Renderer2D {
-    ctx:        CanvasRenderingContext2d,
+    backend:    Rc<RenderBackend>,
     sz:         Vector2,
     camera_pos: Vector2,
+    offset:     Vector2,
 }
 
 
 impl Renderer2D {
     /// Constructor for 2D renderer.
     /// # Arguments
-    /// `canvas` - Reference to the canvas element where the context lives.
-    pub fn new(canvas: &CanvasElement) -> Self {
+    /// `backend` - Backend which will perform the actual drawing.
+    pub fn new(backend: Rc<RenderBackend>) -> Self {
         Renderer2D {
-            ctx: canvas.get_context().unwrap(),
-            sz:  Vector2 { x: canvas.width() as f64, y: canvas.height() as f64 },
+            sz:  backend.size(),
+            backend: backend,
             camera_pos: Vector2::zero(),
+            offset:     Vector2::zero(),
         }
     }
 
+    /// Yields the size of the drawing surface.
+    pub fn size(&self) -> Vector2 {
+        self.sz
+    }
+
     pub fn update_camera_position(&mut self, new_position: Vector2) {
         self.camera_pos = new_position;
     }
 
+    /// Sets an offset to be added to every relative drawing
+    /// operation, on top of the camera position. Used to draw
+    /// objects at interpolated positions.
+    pub fn set_draw_offset(&mut self, offset: Vector2) {
+        self.offset = offset;
+    }
+
     pub fn make_position_relative(&self, absolute_pos: Vector2) -> Vector2 {
-        absolute_pos - self.camera_pos
+        absolute_pos - self.camera_pos + self.offset
     }
     
     /// Draws a colored box.
     pub fn draw_box(&self, color: &str, pos: Vector2, size: Vector2) {
-        self.ctx.set_fill_style_color(color);
-        self.ctx.fill_rect(pos.x, pos.y, size.x, size.y);
+        self.backend.draw_box(color, pos, size);
     }
 
     pub fn draw_box_rel(&self, color: &str, pos: Vector2, size: Vector2) {
@@ -49,11 +131,7 @@ impl Renderer2D {
 
     /// Draws a colored circle.
     pub fn draw_circle(&self, color: &str, pos: Vector2, radius: f64) {
-        self.ctx.begin_path();
-        self.ctx.set_fill_style_color(color);
-        self.ctx.arc(pos.x, pos.y, radius, 0.0, PI * 2.0, false);
-        self.ctx.fill(FillRule::NonZero);
-        self.ctx.close_path();
+        self.backend.draw_circle(color, pos, radius);
     }
 
     pub fn draw_circle_rel(&self, color: &str, pos: Vector2, radius: f64) {
@@ -61,45 +139,118 @@ impl Renderer2D {
         self.draw_circle(color, pos, radius);
     }
 
-    pub fn draw_image(&self, img: ImageElement, pos: Vector2) {
-        let _ = self.ctx.draw_image(img, pos.x, pos.y);
+    /// Draws a colored, convex polygon.
+    pub fn draw_polygon(&self, color: &str, points: &[Vector2]) {
+        self.backend.draw_polygon(color, points);
+    }
+
+    pub fn draw_polygon_rel(&self, color: &str, points: &[Vector2]) {
+        let points: Vec<Vector2> = points.iter()
+            .map(|point| self.make_position_relative(*point))
+            .collect();
+        self.draw_polygon(color, &points);
+    }
+
+
+    /// Draws an oriented box, for debugging.
+    pub fn draw_obb(&self, color: &str, obb: &OBB) {
+        self.draw_polygon(color, &obb.corners());
+    }
+
+    pub fn draw_obb_rel(&self, color: &str, obb: &OBB) {
+        self.draw_polygon_rel(color, &obb.corners());
+    }
+
+    /// Draws a capsule, for debugging.
+    pub fn draw_capsule(&self, color: &str, capsule: &Capsule) {
+        // A single outline, so that translucent capsules are only
+        // blended once: half a circle around each end, joined by
+        // the sides
+        let direction = match (capsule.end - capsule.start).normalized() {
+            direction if direction.length() == 0.0 => Vector2::new(1.0, 0.0),
+            direction => direction,
+        };
+        let side = Vector2::new(-direction.y, direction.x);
+        let mut points = Vec::with_capacity(2 * (CAPSULE_ARC_SEGMENTS + 1));
+        for &(center, turn) in &[(capsule.end, -1.0), (capsule.start, 1.0)] {
+            for i in 0..(CAPSULE_ARC_SEGMENTS + 1) {
+                let angle = PI * (i as f64 / CAPSULE_ARC_SEGMENTS as f64 - 0.5);
+                let offset = direction * angle.cos() * -turn + side * angle.sin() * turn;
+                points.push(center + offset * capsule.radius);
+            }
+        }
+        self.draw_polygon(color, &points);
+    }
+
+    pub fn draw_capsule_rel(&self, color: &str, capsule: &Capsule) {
+        let capsule = Capsule {
+            start:  self.make_position_relative(capsule.start),
+            end:    self.make_position_relative(capsule.end),
+            radius: capsule.radius,
+        };
+        self.draw_capsule(color, &capsule);
+    }
+
+    /// Draws a convex polygon, for debugging.
+    pub fn draw_convex_polygon(&self, color: &str, polygon: &ConvexPolygon) {
+        self.draw_polygon(color, &polygon.vertices);
+    }
+
+    pub fn draw_convex_polygon_rel(&self, color: &str, polygon: &ConvexPolygon) {
+        self.draw_polygon_rel(color, &polygon.vertices);
+    }
+
+
+    pub fn draw_image(&self, source: &str, pos: Vector2) {
+        self.backend.draw_image(source, pos);
     }
 
-    pub fn draw_image_rel(&self, img: ImageElement, pos: Vector2) {
+    pub fn draw_image_rel(&self, source: &str, pos: Vector2) {
         let pos = self.make_position_relative(pos);
-        self.draw_image(img, pos);
+        self.draw_image(source, pos);
     }
 
-    pub fn draw_tile(&self, img: ImageElement, pos: Vector2, tile_size: Vector2, frame: u32) {
+    pub fn draw_tile(&self, source: &str, pos: Vector2, tile_size: Vector2, frame: u32) {
         // Correct animation position
         let pos = pos - Vector2::new(tile_size.x / 2.0, tile_size.y / 2.0);
-        
-        // Calculate frame position
-        let img_size = Vector2::new(img.width() as f64, img.height() as f64);
-        let max_x_frames = (img_size.x / tile_size.x).floor() as u32;
-
-        if max_x_frames == 0 { return; }
-
-        let cows_and_rows = Vector2::new((frame % max_x_frames) as f64,
-                                         (frame / max_x_frames) as f64);
-        
-        let frame_pos = Vector2::new(cows_and_rows.x * tile_size.x,
-                                     cows_and_rows.y * tile_size.y);
-        
-        let _ = self.ctx.draw_image_s(img,
-                                      frame_pos.x, frame_pos.y,
-                                      tile_size.x, tile_size.y,
-                                      pos.x, pos.y,
-                                      tile_size.x, tile_size.y);
-    }
-
-    pub fn draw_tile_rel(&self, img: ImageElement, pos: Vector2, tile_size: Vector2, frame: u32) {
+        self.backend.draw_tile(source, pos, tile_size, frame);
+    }
+
+    pub fn draw_tile_rel(&self, source: &str, pos: Vector2, tile_size: Vector2, frame: u32) {
         let pos = self.make_position_relative(pos);
-        self.draw_tile(img, pos, tile_size, frame);
+        self.draw_tile(source, pos, tile_size, frame);
     }
     
     /// Clears the screen.
     pub fn clear(&self) {
-        self.ctx.clear_rect(0.0, 0.0, self.sz.x, self.sz.y);
+        self.backend.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use render::software::SoftwareBackend;
+
+    #[test]
+    fn translucent_capsules_blend_once() {
+        let backend = Rc::new(SoftwareBackend::new(40, 20));
+        let renderer = Renderer2D::new(backend.clone());
+        backend.draw_box("#00f", Vector2::zero(), Vector2::new(40.0, 20.0));
+        renderer.draw_capsule("#ff00007f", &Capsule {
+            start:  Vector2::new(10.0, 10.0),
+            end:    Vector2::new(30.0, 10.0),
+            radius: 6.0,
+        });
+
+        let blended = 0x7f0080ff;
+        assert!(backend.framebuffer().iter().all(|&pixel| pixel == 0x0000ffff || pixel == blended));
+
+        // Middle, both rounded ends, and the corners they leave out
+        assert_eq!(backend.pixel(20, 5), Some(blended));
+        assert_eq!(backend.pixel(5, 10), Some(blended));
+        assert_eq!(backend.pixel(34, 10), Some(blended));
+        assert_eq!(backend.pixel(4, 4), Some(0x0000ffff));
+        assert_eq!(backend.pixel(35, 15), Some(0x0000ffff));
     }
 }
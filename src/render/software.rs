@@ -0,0 +1,524 @@
+//! Submodule for rendering on a plain memory framebuffer.
+//! This backend does not depend on a browser, so it can be used
+//! to run the game natively and on tests.
+
+use types::Vector2;
+use render::RenderBackend;
+use std::cell::{ Ref, RefCell };
+use std::collections::HashMap;
+
+/// Color used to draw tiles and images which were never loaded
+/// into the backend, and shapes whose color can't be parsed.
+const PLACEHOLDER_COLOR: u32 = 0xff00ffff;
+
+/// Size of the box drawn in place of an image which was never
+/// loaded, since its real size is unknown.
+const PLACEHOLDER_SIZE: f64 = 16.0;
+
+/// Represents an image which can be drawn by the software backend.
+/// Pixels are stored row by row, as `0xRRGGBBAA`.
+#[derive(Clone, Debug)]
+pub struct SoftwareImage {
+    pub width:  usize,
+    pub height: usize,
+    pub pixels: Vec<u32>,
+}
+
+impl SoftwareImage {
+    /// Creates a new image filled with a single color.
+    /// # Arguments
+    /// * `width` - Width of the image, in pixels.
+    ///
+    /// * `height` - Height of the image, in pixels.
+    ///
+    /// * `color` - Color of every pixel, as `0xRRGGBBAA`.
+    pub fn filled(width: usize, height: usize, color: u32) -> Self {
+        SoftwareImage {
+            width:  width,
+            height: height,
+            pixels: vec![color; width * height],
+        }
+    }
+}
+
+/// Render backend which draws into a memory framebuffer.
+/// Pixels are stored row by row, as `0xRRGGBBAA`.
+pub struct SoftwareBackend {
+    width:  usize,
+    height: usize,
+    pixels: RefCell<Vec<u32>>,
+    images: RefCell<HashMap<String, SoftwareImage>>,
+}
+
+impl SoftwareBackend {
+    /// Creates a new, cleared framebuffer.
+    /// # Arguments
+    /// * `width` - Width of the framebuffer, in pixels.
+    ///
+    /// * `height` - Height of the framebuffer, in pixels.
+    pub fn new(width: usize, height: usize) -> Self {
+        SoftwareBackend {
+            width:  width,
+            height: height,
+            pixels: RefCell::new(vec![0; width * height]),
+            images: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Registers an image under a source path, so that it can
+    /// be drawn later. Images which were not loaded are drawn
+    /// as placeholder boxes.
+    pub fn load_image(&self, source: &str, image: SoftwareImage) {
+        self.images.borrow_mut().insert(String::from(source), image);
+    }
+
+    /// Yields the color of a single pixel, or `None` if the
+    /// coordinates are out of bounds.
+    pub fn pixel(&self, x: usize, y: usize) -> Option<u32> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        Some(self.pixels.borrow()[y * self.width + x])
+    }
+
+    /// Yields the whole framebuffer.
+    pub fn framebuffer<'a>(&'a self) -> Ref<'a, Vec<u32>> {
+        self.pixels.borrow()
+    }
+
+    /// Blends a color on top of a pixel. Out-of-bounds pixels
+    /// are ignored.
+    fn plot(&self, pixels: &mut [u32], x: i64, y: i64, color: u32) {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return;
+        }
+        let index = y as usize * self.width + x as usize;
+        pixels[index] = blend(pixels[index], color);
+    }
+
+    /// Yields the pixels from `min` up to `max` which are on the
+    /// framebuffer, so that shapes far off screen cost nothing to
+    /// draw. Both points should already be rounded to whole pixels.
+    fn on_screen(&self, min: Vector2, max: Vector2) -> ((i64, i64), (i64, i64)) {
+        // Clamp before converting, since huge values don't fit
+        let clamp = |value: f64, limit: usize| {
+            if value <= 0.0 {
+                0
+            } else if value >= limit as f64 {
+                limit as i64
+            } else {
+                value as i64
+            }
+        };
+        ((clamp(min.x, self.width), clamp(min.y, self.height)),
+         (clamp(max.x, self.width), clamp(max.y, self.height)))
+    }
+
+    /// Fills a box with the placeholder color, showing where
+    /// something which was never loaded would be.
+    fn placeholder(&self, pos: Vector2, size: Vector2) {
+        let mut pixels = self.pixels.borrow_mut();
+        let min = Vector2::new(pos.x.round(), pos.y.round());
+        let (min, max) = self.on_screen(min, min + Vector2::new(size.x.trunc(), size.y.trunc()));
+        for y in min.1..max.1 {
+            for x in min.0..max.0 {
+                self.plot(&mut pixels, x, y, PLACEHOLDER_COLOR);
+            }
+        }
+    }
+
+    /// Copies a rectangular region of an image into the framebuffer.
+    fn blit(&self, image: &SoftwareImage, src: (usize, usize),
+            size: (usize, usize), pos: Vector2) {
+        let mut pixels = self.pixels.borrow_mut();
+        let origin = (pos.x.round() as i64, pos.y.round() as i64);
+        for row in 0..size.1 {
+            let src_y = src.1 + row;
+            if src_y >= image.height { break; }
+            for col in 0..size.0 {
+                let src_x = src.0 + col;
+                if src_x >= image.width { break; }
+                let color = image.pixels[src_y * image.width + src_x];
+                self.plot(&mut pixels, origin.0 + col as i64, origin.1 + row as i64, color);
+            }
+        }
+    }
+}
+
+impl RenderBackend for SoftwareBackend {
+    fn size(&self) -> Vector2 {
+        Vector2::new(self.width as f64, self.height as f64)
+    }
+
+    fn draw_box(&self, color: &str, pos: Vector2, size: Vector2) {
+        let color = fill_color(color);
+        let mut pixels = self.pixels.borrow_mut();
+        let (min, max) = self.on_screen(Vector2::new(pos.x.round(), pos.y.round()),
+                                        Vector2::new((pos.x + size.x).round(), (pos.y + size.y).round()));
+        for y in min.1..max.1 {
+            for x in min.0..max.0 {
+                self.plot(&mut pixels, x, y, color);
+            }
+        }
+    }
+
+    fn draw_circle(&self, color: &str, pos: Vector2, radius: f64) {
+        let color = fill_color(color);
+        let mut pixels = self.pixels.borrow_mut();
+        let (min, max) = self.on_screen(Vector2::new((pos.x - radius).floor(), (pos.y - radius).floor()),
+                                        Vector2::new((pos.x + radius).ceil(), (pos.y + radius).ceil()));
+        let sq_radius = radius * radius;
+        for y in min.1..max.1 {
+            for x in min.0..max.0 {
+                // Test against the center of the pixel
+                let d = Vector2::new(x as f64 + 0.5, y as f64 + 0.5) - pos;
+                if d.dot(d) <= sq_radius {
+                    self.plot(&mut pixels, x, y, color);
+                }
+            }
+        }
+    }
+
+    fn draw_polygon(&self, color: &str, points: &[Vector2]) {
+        if points.len() < 3 { return; }
+
+        let color = fill_color(color);
+        let mut pixels = self.pixels.borrow_mut();
+
+        // Only look at pixels around the polygon, and on screen
+        let mut min = points[0];
+        let mut max = points[0];
+        for point in points {
+            min = Vector2::new(min.x.min(point.x), min.y.min(point.y));
+            max = Vector2::new(max.x.max(point.x), max.y.max(point.y));
+        }
+        let (min, max) = self.on_screen(Vector2::new(min.x.floor(), min.y.floor()),
+                                        Vector2::new(max.x.ceil(), max.y.ceil()));
+
+        for y in min.1..max.1 {
+            for x in min.0..max.0 {
+                // A pixel center is inside a convex polygon when it is
+                // on the same side of every edge
+                let p = Vector2::new(x as f64 + 0.5, y as f64 + 0.5);
+                let mut sign = 0.0;
+                let mut inside = true;
+                for i in 0..points.len() {
+                    let a = points[i];
+                    let b = points[(i + 1) % points.len()];
+                    let cross = (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x);
+                    if cross == 0.0 { continue; }
+                    if sign == 0.0 {
+                        sign = cross.signum();
+                    } else if cross.signum() != sign {
+                        inside = false;
+                        break;
+                    }
+                }
+                if inside {
+                    self.plot(&mut pixels, x, y, color);
+                }
+            }
+        }
+    }
+
+    fn draw_image(&self, source: &str, pos: Vector2) {
+        let images = self.images.borrow();
+        match images.get(source) {
+            Some(image) => self.blit(image, (0, 0), (image.width, image.height), pos),
+            None => self.placeholder(pos, Vector2::new(PLACEHOLDER_SIZE, PLACEHOLDER_SIZE)),
+        }
+    }
+
+    fn draw_tile(&self, source: &str, pos: Vector2, tile_size: Vector2, frame: u32) {
+        let images = self.images.borrow();
+        let image = match images.get(source) {
+            Some(image) => image,
+            None => {
+                // Nothing to sample from, so just show where
+                // the tile would be.
+                self.placeholder(pos, tile_size);
+                return;
+            },
+        };
+
+        let tile = (tile_size.x as usize, tile_size.y as usize);
+        if tile.0 == 0 { return; }
+        let max_x_frames = image.width / tile.0;
+        if max_x_frames == 0 { return; }
+
+        let frame = frame as usize;
+        let src = ((frame % max_x_frames) * tile.0,
+                   (frame / max_x_frames) * tile.1);
+        self.blit(image, src, tile, pos);
+    }
+
+    fn clear(&self) {
+        for pixel in self.pixels.borrow_mut().iter_mut() {
+            *pixel = 0;
+        }
+    }
+}
+
+
+// ================================
+
+/// Parses a CSS color into `0xRRGGBBAA`. Hex colors (`#rgb`,
+/// `#rgba`, `#rrggbb` or `#rrggbbaa`), `rgb(r, g, b)`,
+/// `rgba(r, g, b, a)` and the basic named colors are understood;
+/// anything else yields an error.
+pub fn parse_color(color: &str) -> Result<u32, &'static str> {
+    let color = color.trim();
+    let mut chars = color.chars();
+    if chars.next() == Some('#') {
+        return parse_hex_color(chars.as_str());
+    }
+
+    match color.find('(') {
+        Some(open) if color.ends_with(')') => {
+            let arguments = &color[open + 1..color.len() - 1];
+            match &color[..open] {
+                "rgb"  => parse_rgb_color(arguments, false),
+                "rgba" => parse_rgb_color(arguments, true),
+                _      => Err("Unknown color function"),
+            }
+        },
+        _ => named_color(&color.to_lowercase()).ok_or("Unknown color name"),
+    }
+}
+
+/// Parses a color for drawing. Colors which can't be parsed are
+/// drawn with the placeholder color, so that they stand out.
+fn fill_color(color: &str) -> u32 {
+    parse_color(color).unwrap_or(PLACEHOLDER_COLOR)
+}
+
+fn parse_hex_color(hex: &str) -> Result<u32, &'static str> {
+    let digits: Vec<u32> = hex.chars()
+        .filter_map(|c| c.to_digit(16))
+        .collect();
+    if digits.len() != hex.len() {
+        return Err("Invalid hex color");
+    }
+
+    match digits.len() {
+        3 | 4 => {
+            // Short form: each digit is repeated, and alpha is
+            // opaque unless given
+            let alpha = if digits.len() == 4 { digits[3] } else { 0xf };
+            let mut value = 0;
+            for &digit in digits.iter().take(3).chain(Some(&alpha)) {
+                value = (value << 8) | (digit << 4) | digit;
+            }
+            Ok(value)
+        },
+        6 | 8 => {
+            let mut value = 0;
+            for digit in &digits {
+                value = (value << 4) | digit;
+            }
+            Ok(if digits.len() == 6 { (value << 8) | 0xff } else { value })
+        },
+        _ => Err("Invalid hex color"),
+    }
+}
+
+/// Parses the arguments of `rgb(...)` or `rgba(...)`: channels go
+/// from 0 to 255, and alpha from 0 to 1.
+fn parse_rgb_color(arguments: &str, has_alpha: bool) -> Result<u32, &'static str> {
+    let values: Vec<f64> = arguments.split(',')
+        .map(|value| value.trim().parse::<f64>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| "Invalid rgb color")?;
+    if values.len() != if has_alpha { 4 } else { 3 } {
+        return Err("Invalid rgb color");
+    }
+
+    let mut value = 0;
+    for &channel in values.iter().take(3) {
+        value = (value << 8) | bounded(channel, 255.0)?.round() as u32;
+    }
+
+    let alpha = if has_alpha { bounded(values[3], 1.0)? } else { 1.0 };
+    Ok((value << 8) | (alpha * 255.0).round() as u32)
+}
+
+/// Yields a color component if it goes from 0 up to `max`.
+fn bounded(value: f64, max: f64) -> Result<f64, &'static str> {
+    if value.is_nan() || value < 0.0 {
+        return Err("Invalid rgb color");
+    }
+    if value > max {
+        return Err("Invalid rgb color");
+    }
+    Ok(value)
+}
+
+/// Yields one of the basic named colors, given in lowercase.
+fn named_color(name: &str) -> Option<u32> {
+    Some(match name {
+        "transparent" => 0x00000000,
+        "black"       => 0x000000ff,
+        "silver"      => 0xc0c0c0ff,
+        "gray"        => 0x808080ff,
+        "grey"        => 0x808080ff,
+        "white"       => 0xffffffff,
+        "maroon"      => 0x800000ff,
+        "red"         => 0xff0000ff,
+        "purple"      => 0x800080ff,
+        "fuchsia"     => 0xff00ffff,
+        "magenta"     => 0xff00ffff,
+        "green"       => 0x008000ff,
+        "lime"        => 0x00ff00ff,
+        "olive"       => 0x808000ff,
+        "yellow"      => 0xffff00ff,
+        "navy"        => 0x000080ff,
+        "blue"        => 0x0000ffff,
+        "teal"        => 0x008080ff,
+        "aqua"        => 0x00ffffff,
+        "cyan"        => 0x00ffffff,
+        "orange"      => 0xffa500ff,
+        _             => return None,
+    })
+}
+
+/// Blends a `0xRRGGBBAA` color over another one.
+fn blend(dst: u32, src: u32) -> u32 {
+    let src_a = (src & 0xff) as f64 / 255.0;
+    let dst_a = (dst & 0xff) as f64 / 255.0;
+    let out_a = src_a + dst_a * (1.0 - src_a);
+    if out_a <= 0.0 {
+        return 0;
+    }
+
+    let mut out = 0;
+    for &shift in &[24u32, 16, 8] {
+        let s = ((src >> shift) & 0xff) as f64;
+        let d = ((dst >> shift) & 0xff) as f64;
+        let c = (s * src_a + d * dst_a * (1.0 - src_a)) / out_a;
+        out |= (c.round() as u32 & 0xff) << shift;
+    }
+    out | (out_a * 255.0).round() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED:  u32 = 0xff0000ff;
+    const BLUE: u32 = 0x0000ffff;
+
+    #[test]
+    fn clear_empties_the_framebuffer() {
+        let backend = SoftwareBackend::new(8, 8);
+        backend.draw_box("#f00", Vector2::zero(), Vector2::new(8.0, 8.0));
+        assert_eq!(backend.pixel(3, 3), Some(RED));
+
+        backend.clear();
+        assert!(backend.framebuffer().iter().all(|&pixel| pixel == 0));
+    }
+
+    #[test]
+    fn boxes_cover_their_area_only() {
+        let backend = SoftwareBackend::new(8, 8);
+        backend.draw_box("#f00", Vector2::new(2.0, 2.0), Vector2::new(3.0, 3.0));
+
+        assert_eq!(backend.pixel(2, 2), Some(RED));
+        assert_eq!(backend.pixel(4, 4), Some(RED));
+        assert_eq!(backend.pixel(5, 5), Some(0));
+        assert_eq!(backend.pixel(1, 2), Some(0));
+        assert_eq!(backend.pixel(8, 0), None);
+    }
+
+    #[test]
+    fn translucent_boxes_blend() {
+        let backend = SoftwareBackend::new(4, 4);
+        backend.draw_box("#00f", Vector2::zero(), Vector2::new(4.0, 4.0));
+        backend.draw_box("#ff00007f", Vector2::zero(), Vector2::new(4.0, 4.0));
+
+        assert_eq!(backend.pixel(0, 0), Some(0x7f0080ff));
+    }
+
+    #[test]
+    fn circles_cover_pixel_centers_within_radius() {
+        let backend = SoftwareBackend::new(20, 20);
+        backend.draw_circle("#f00", Vector2::new(10.0, 10.0), 3.0);
+
+        assert_eq!(backend.pixel(10, 10), Some(RED));
+        assert_eq!(backend.pixel(10, 12), Some(RED));
+        assert_eq!(backend.pixel(7, 7), Some(0));
+        assert_eq!(backend.pixel(10, 14), Some(0));
+    }
+
+    #[test]
+    fn tiles_sample_their_frame() {
+        let backend = SoftwareBackend::new(8, 8);
+        // Two 2x2 frames side by side: red, then blue
+        let mut image = SoftwareImage::filled(4, 2, RED);
+        for y in 0..2 {
+            for x in 2..4 {
+                image.pixels[y * 4 + x] = BLUE;
+            }
+        }
+        backend.load_image("frames.png", image);
+
+        backend.draw_tile("frames.png", Vector2::new(1.0, 1.0), Vector2::new(2.0, 2.0), 1);
+        assert_eq!(backend.pixel(1, 1), Some(BLUE));
+        assert_eq!(backend.pixel(2, 2), Some(BLUE));
+        assert_eq!(backend.pixel(3, 3), Some(0));
+
+        backend.draw_tile("frames.png", Vector2::new(4.0, 4.0), Vector2::new(2.0, 2.0), 0);
+        assert_eq!(backend.pixel(5, 5), Some(RED));
+    }
+
+    #[test]
+    fn missing_images_draw_placeholders() {
+        let backend = SoftwareBackend::new(40, 40);
+        backend.draw_tile("missing.png", Vector2::zero(), Vector2::new(4.0, 4.0), 3);
+        assert_eq!(backend.pixel(3, 3), Some(PLACEHOLDER_COLOR));
+        assert_eq!(backend.pixel(4, 4), Some(0));
+
+        backend.draw_image("missing.png", Vector2::new(20.0, 20.0));
+        assert_eq!(backend.pixel(20, 20), Some(PLACEHOLDER_COLOR));
+        assert_eq!(backend.pixel(35, 35), Some(PLACEHOLDER_COLOR));
+        assert_eq!(backend.pixel(36, 36), Some(0));
+    }
+
+    #[test]
+    fn shapes_off_screen_are_clamped() {
+        let backend = SoftwareBackend::new(8, 8);
+
+        // Far away and huge: nothing is drawn, and quickly
+        backend.draw_box("#f00", Vector2::new(1e12, -1e12), Vector2::new(1e12, 1e12));
+        backend.draw_box("#f00", Vector2::new(-1e12, 0.0), Vector2::new(1e12 - 100.0, 8.0));
+        assert!(backend.framebuffer().iter().all(|&pixel| pixel == 0));
+
+        // Much larger than the framebuffer: everything is covered
+        backend.draw_circle("#f00", Vector2::new(4.0, 4.0), 1e9);
+        assert!(backend.framebuffer().iter().all(|&pixel| pixel == RED));
+    }
+
+    #[test]
+    fn colors_are_parsed() {
+        assert_eq!(parse_color("#f00"), Ok(RED));
+        assert_eq!(parse_color("#f008"), Ok(0xff000088));
+        assert_eq!(parse_color("#0000ff"), Ok(BLUE));
+        assert_eq!(parse_color("#ff00007f"), Ok(0xff00007f));
+        assert_eq!(parse_color("rgb(255, 0, 0)"), Ok(RED));
+        assert_eq!(parse_color("rgba(0, 0, 255, 0.5)"), Ok(0x0000ff80));
+        assert_eq!(parse_color("Blue"), Ok(BLUE));
+        assert_eq!(parse_color("transparent"), Ok(0));
+
+        assert!(parse_color("#ff000").is_err());
+        assert!(parse_color("#gg0000").is_err());
+        assert!(parse_color("rgb(256, 0, 0)").is_err());
+        assert!(parse_color("rgba(0, 0, 0)").is_err());
+        assert!(parse_color("reddish").is_err());
+    }
+
+    #[test]
+    fn unknown_colors_draw_placeholders() {
+        let backend = SoftwareBackend::new(4, 4);
+        backend.draw_box("reddish", Vector2::zero(), Vector2::new(4.0, 4.0));
+        assert_eq!(backend.pixel(2, 2), Some(PLACEHOLDER_COLOR));
+    }
+}
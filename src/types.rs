@@ -60,6 +60,23 @@ impl Vector2 {
     pub fn dot(&self, rhs: Vector2) -> f64 {
         (self.x * rhs.x) + (self.y * rhs.y)
     }
+
+    /// Returns the length (magnitude) of this vector.
+    pub fn length(&self) -> f64 {
+        self.dot(*self).sqrt()
+    }
+
+    /// Returns a vector with the same direction as this
+    /// one, but with unitary length. The zero vector is
+    /// returned as is.
+    pub fn normalized(&self) -> Vector2 {
+        let length = self.length();
+        if length == 0.0 {
+            *self
+        } else {
+            *self * (1.0 / length)
+        }
+    }
 }
 
 
@@ -85,3 +102,14 @@ impl ops::Sub<Vector2> for Vector2 {
         }
     }
 }
+
+impl ops::Mul<f64> for Vector2 {
+    type Output = Vector2;
+
+    fn mul(self, rhs: f64) -> Vector2 {
+        Vector2 {
+            x: self.x * rhs,
+            y: self.y * rhs
+        }
+    }
+}